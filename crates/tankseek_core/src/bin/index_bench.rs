@@ -0,0 +1,88 @@
+// Compares memory usage and lookup latency of the bigram and n-gram indexes
+
+use std::time::{Duration, Instant};
+
+use tankseek_core::file_tree::{ElementId, FileTree};
+use tankseek_core::indexer::bigram_index::{self, BigramIndex};
+use tankseek_core::indexer::text_index::{NgramIndex, TextIndex};
+
+const QUERIES: &[&str] = &[".jpg", ".json", "readme", "config", "2023", "brand", "node_modules", "index.html"];
+const RUNS: u32 = 10;
+
+fn main() {
+    let path = std::env::args().nth(1).unwrap_or_else(|| "filelist.efu".to_string());
+    let start_time = Instant::now();
+    let tree = tankseek_core::loader::efu::import_efu(&path).expect("Failed to load file tree");
+    println!("Loaded {} elements from {} in {:?}", tree.len(), path, start_time.elapsed());
+
+    let start_time = Instant::now();
+    let bigram_index = BigramIndex::new(&tree);
+    let bigram_time = start_time.elapsed();
+    let start_time = Instant::now();
+    BigramIndex::with_threads(&tree, 1);
+    let single_thread_time = start_time.elapsed();
+    let mut indexes: Vec<(String, Box<dyn TextIndex>, Duration)> =
+        vec![("bigram".to_string(), Box::new(bigram_index), bigram_time)];
+    for n in 3..=4 {
+        let start_time = Instant::now();
+        let ngram_index = NgramIndex::new(&tree, n);
+        indexes.push((format!("{}-gram", n), Box::new(ngram_index), start_time.elapsed()));
+    }
+
+    println!();
+    println!(
+        "Bigram build: {:?} with 1 thread, {:?} with {} threads",
+        single_thread_time,
+        bigram_time,
+        bigram_index::default_threads()
+    );
+    println!("{:<8} {:>10} {:>14} {:>12}", "index", "grams", "memory (KiB)", "build");
+    for (name, index, build_time) in &indexes {
+        println!(
+            "{:<8} {:>10} {:>14} {:>12?}",
+            name,
+            index.len(),
+            index.memory_usage() / 1024,
+            build_time
+        );
+    }
+
+    println!();
+    println!(
+        "{:<14} {:<8} {:>10} {:>8} {:>12} {:>12}",
+        "query", "index", "candidates", "matches", "lookup", "with filter"
+    );
+    for query in QUERIES {
+        for (name, index, _) in &indexes {
+            let (candidates, lookup_time) = time_runs(|| index.candidates(query));
+            let (matches, total_time) = time_runs(|| filter(&tree, index.candidates(query), query));
+            println!(
+                "{:<14} {:<8} {:>10} {:>8} {:>12?} {:>12?}",
+                query,
+                name,
+                candidates.len(),
+                matches.len(),
+                lookup_time,
+                total_time
+            );
+        }
+    }
+}
+
+// Checks the candidates against the filenames, like the query evaluator does
+fn filter(tree: &FileTree, candidates: Vec<ElementId>, query: &str) -> Vec<ElementId> {
+    candidates
+        .into_iter()
+        .filter(|&index| tree.get_filename(index).to_lowercase().contains(query))
+        .collect()
+}
+
+// Runs the function a few times and returns its result and the average duration
+fn time_runs<T, F: Fn() -> T>(function: F) -> (T, Duration) {
+    let start_time = Instant::now();
+    let mut result = function();
+    for _ in 1..RUNS {
+        result = function();
+    }
+    (result, start_time.elapsed() / RUNS)
+}
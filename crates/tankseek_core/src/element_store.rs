@@ -0,0 +1,307 @@
+use std::collections::HashMap;
+
+use crate::file_tree::{Element, ElementId, Filename, RawId};
+use crate::memory::{MemoryUsage, hash_map_bytes, vec_bytes};
+
+// Storage of the elements of a FileTree. While the tree is built every element is a struct with
+// its own list of children, freezing converts them into one column per field.
+pub(crate) enum ElementStore {
+    Elements(Vec<Element>),
+    Frozen(Box<FrozenElements>),
+}
+impl ElementStore {
+    pub fn freeze(&mut self) {
+        match self {
+            ElementStore::Elements(elements) => {
+                *self = ElementStore::Frozen(Box::new(FrozenElements::from_elements(std::mem::take(elements))));
+            }
+            ElementStore::Frozen(frozen) => frozen.compact_children(),
+        }
+    }
+    pub fn is_frozen(&self) -> bool {
+        matches!(self, ElementStore::Frozen(_))
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            ElementStore::Elements(elements) => elements.len(),
+            ElementStore::Frozen(frozen) => frozen.parents.len(),
+        }
+    }
+    pub fn push(&mut self, element: Element) {
+        match self {
+            ElementStore::Elements(elements) => elements.push(element),
+            ElementStore::Frozen(frozen) => frozen.push(element),
+        }
+    }
+
+    pub fn filename(&self, index: ElementId) -> Filename {
+        match self {
+            ElementStore::Elements(elements) => elements[index.index()].filename,
+            ElementStore::Frozen(frozen) => frozen.filenames[index.index()],
+        }
+    }
+    pub fn parent(&self, index: ElementId) -> ElementId {
+        match self {
+            ElementStore::Elements(elements) => elements[index.index()].parent,
+            ElementStore::Frozen(frozen) => frozen.parents[index.index()],
+        }
+    }
+    pub fn children(&self, index: ElementId) -> &[ElementId] {
+        match self {
+            ElementStore::Elements(elements) => &elements[index.index()].children,
+            ElementStore::Frozen(frozen) => frozen.children(index),
+        }
+    }
+    pub fn size(&self, index: ElementId) -> Option<i64> {
+        match self {
+            ElementStore::Elements(elements) => elements[index.index()].size,
+            ElementStore::Frozen(frozen) => frozen.sizes.get(index.index()),
+        }
+    }
+    pub fn date_modified(&self, index: ElementId) -> Option<i64> {
+        match self {
+            ElementStore::Elements(elements) => elements[index.index()].date_modified,
+            ElementStore::Frozen(frozen) => frozen.dates_modified.get(index.index()),
+        }
+    }
+    pub fn date_created(&self, index: ElementId) -> Option<i64> {
+        match self {
+            ElementStore::Elements(elements) => elements[index.index()].date_created,
+            ElementStore::Frozen(frozen) => frozen.dates_created.get(index.index()),
+        }
+    }
+    pub fn attributes(&self, index: ElementId) -> u32 {
+        match self {
+            ElementStore::Elements(elements) => elements[index.index()].attributes,
+            ElementStore::Frozen(frozen) => frozen.attributes[index.index()],
+        }
+    }
+
+    pub fn set_filename(&mut self, index: ElementId, filename: Filename) {
+        match self {
+            ElementStore::Elements(elements) => elements[index.index()].filename = filename,
+            ElementStore::Frozen(frozen) => frozen.filenames[index.index()] = filename,
+        }
+    }
+    pub fn set_parent(&mut self, index: ElementId, parent: ElementId) {
+        match self {
+            ElementStore::Elements(elements) => elements[index.index()].parent = parent,
+            ElementStore::Frozen(frozen) => frozen.parents[index.index()] = parent,
+        }
+    }
+    pub fn set_metadata(
+        &mut self,
+        index: ElementId,
+        size: Option<i64>,
+        date_modified: Option<i64>,
+        date_created: Option<i64>,
+        attributes: u32,
+    ) {
+        match self {
+            ElementStore::Elements(elements) => {
+                let element = &mut elements[index.index()];
+                element.size = size;
+                element.date_modified = date_modified;
+                element.date_created = date_created;
+                element.attributes = attributes;
+            }
+            ElementStore::Frozen(frozen) => {
+                frozen.sizes.set(index.index(), size);
+                frozen.dates_modified.set(index.index(), date_modified);
+                frozen.dates_created.set(index.index(), date_created);
+                frozen.attributes[index.index()] = attributes;
+            }
+        }
+    }
+    pub fn children_mut(&mut self, index: ElementId) -> &mut Vec<ElementId> {
+        match self {
+            ElementStore::Elements(elements) => &mut elements[index.index()].children,
+            ElementStore::Frozen(frozen) => frozen.children_mut(index),
+        }
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        match self {
+            ElementStore::Elements(elements) => elements.shrink_to_fit(),
+            ElementStore::Frozen(frozen) => frozen.shrink_to_fit(),
+        }
+    }
+
+    pub fn memory_usage(&self) -> MemoryUsage {
+        match self {
+            ElementStore::Elements(elements) => {
+                let children: usize = elements.iter().map(|element| vec_bytes(&element.children)).sum();
+                MemoryUsage::new("elements").with("structs", vec_bytes(elements)).with("children", children)
+            }
+            ElementStore::Frozen(frozen) => frozen.memory_usage(),
+        }
+    }
+}
+
+// Elements as one column per field. The children of all elements share one array in compressed
+// sparse rows, so there is no allocation per element.
+pub(crate) struct FrozenElements {
+    filenames: Vec<Filename>,
+    parents: Vec<ElementId>,
+    attributes: Vec<u32>,
+    sizes: NullableColumn,
+    dates_modified: NullableColumn,
+    dates_created: NullableColumn,
+    // The children of element i are child_ids[child_offsets[i]..child_offsets[i + 1]]
+    child_offsets: Vec<RawId>,
+    child_ids: Vec<ElementId>,
+    // Children changed since freezing, they replace the rows of their elements until the next freeze
+    changed_children: HashMap<ElementId, Vec<ElementId>>,
+}
+impl FrozenElements {
+    fn from_elements(elements: Vec<Element>) -> Self {
+        let len = elements.len();
+        let num_children = elements.iter().map(|element| element.children.len()).sum();
+        let mut frozen = FrozenElements {
+            filenames: Vec::with_capacity(len),
+            parents: Vec::with_capacity(len),
+            attributes: Vec::with_capacity(len),
+            sizes: NullableColumn::with_capacity(len),
+            dates_modified: NullableColumn::with_capacity(len),
+            dates_created: NullableColumn::with_capacity(len),
+            child_offsets: Vec::with_capacity(len + 1),
+            child_ids: Vec::with_capacity(num_children),
+            changed_children: HashMap::new(),
+        };
+        frozen.child_offsets.push(0);
+        for element in elements {
+            frozen.push(element);
+        }
+        frozen
+    }
+
+    fn push(&mut self, element: Element) {
+        self.filenames.push(element.filename);
+        self.parents.push(element.parent);
+        self.attributes.push(element.attributes);
+        self.sizes.push(element.size);
+        self.dates_modified.push(element.date_modified);
+        self.dates_created.push(element.date_created);
+        self.child_ids.extend_from_slice(&element.children);
+        self.child_offsets.push(self.child_ids.len() as RawId);
+    }
+
+    fn children(&self, index: ElementId) -> &[ElementId] {
+        if !self.changed_children.is_empty()
+            && let Some(children) = self.changed_children.get(&index)
+        {
+            return children;
+        }
+        let (start, end) = (self.child_offsets[index.index()], self.child_offsets[index.index() + 1]);
+        &self.child_ids[start as usize..end as usize]
+    }
+    fn children_mut(&mut self, index: ElementId) -> &mut Vec<ElementId> {
+        let (start, end) = (self.child_offsets[index.index()], self.child_offsets[index.index() + 1]);
+        let row = &self.child_ids[start as usize..end as usize];
+        self.changed_children.entry(index).or_insert_with(|| row.to_vec())
+    }
+
+    // Moves the changed children back into the rows
+    fn compact_children(&mut self) {
+        if self.changed_children.is_empty() {
+            return;
+        }
+        let mut child_offsets = Vec::with_capacity(self.child_offsets.len());
+        let mut child_ids = Vec::with_capacity(self.child_ids.len());
+        child_offsets.push(0);
+        for index in (0..self.parents.len()).map(ElementId::new) {
+            child_ids.extend_from_slice(self.children(index));
+            child_offsets.push(child_ids.len() as RawId);
+        }
+        self.child_offsets = child_offsets;
+        self.child_ids = child_ids;
+        self.changed_children = HashMap::new();
+    }
+
+    fn shrink_to_fit(&mut self) {
+        self.filenames.shrink_to_fit();
+        self.parents.shrink_to_fit();
+        self.attributes.shrink_to_fit();
+        self.sizes.shrink_to_fit();
+        self.dates_modified.shrink_to_fit();
+        self.dates_created.shrink_to_fit();
+        self.child_offsets.shrink_to_fit();
+        self.child_ids.shrink_to_fit();
+    }
+
+    fn memory_usage(&self) -> MemoryUsage {
+        let changed: usize = self.changed_children.values().map(vec_bytes).sum();
+        MemoryUsage::new("elements")
+            .with("filenames", vec_bytes(&self.filenames))
+            .with("parents", vec_bytes(&self.parents))
+            .with("attributes", vec_bytes(&self.attributes))
+            .with("sizes", self.sizes.memory_usage())
+            .with("dates", self.dates_modified.memory_usage() + self.dates_created.memory_usage())
+            .with("children", vec_bytes(&self.child_offsets) + vec_bytes(&self.child_ids))
+            .with("changed_children", hash_map_bytes(&self.changed_children) + changed)
+    }
+}
+
+// Column of optional values with a bitmap of the present ones. Missing values are stored as 0,
+// which halves the size of an Option<i64>.
+struct NullableColumn {
+    values: Vec<i64>,
+    present: Vec<u64>,
+}
+impl NullableColumn {
+    fn with_capacity(capacity: usize) -> Self {
+        NullableColumn {
+            values: Vec::with_capacity(capacity),
+            present: Vec::with_capacity(capacity.div_ceil(64)),
+        }
+    }
+
+    fn get(&self, index: usize) -> Option<i64> {
+        (self.present[index / 64] & (1 << (index % 64)) != 0).then(|| self.values[index])
+    }
+    fn set(&mut self, index: usize, value: Option<i64>) {
+        self.values[index] = value.unwrap_or(0);
+        let bit = 1 << (index % 64);
+        if value.is_some() {
+            self.present[index / 64] |= bit;
+        } else {
+            self.present[index / 64] &= !bit;
+        }
+    }
+    fn push(&mut self, value: Option<i64>) {
+        if self.values.len().is_multiple_of(64) {
+            self.present.push(0);
+        }
+        self.values.push(0);
+        self.set(self.values.len() - 1, value);
+    }
+
+    fn shrink_to_fit(&mut self) {
+        self.values.shrink_to_fit();
+        self.present.shrink_to_fit();
+    }
+    fn memory_usage(&self) -> usize {
+        vec_bytes(&self.values) + vec_bytes(&self.present)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nullable_column() {
+        let mut column = NullableColumn::with_capacity(0);
+        for i in 0..130 {
+            column.push((i % 3 != 0).then_some(i - 65));
+        }
+        assert_eq!(column.get(0), None);
+        assert_eq!(column.get(65), Some(0));
+        assert_eq!(column.get(129), None);
+        column.set(129, Some(7));
+        column.set(1, None);
+        assert_eq!((column.get(129), column.get(1), column.get(2)), (Some(7), None, Some(-63)));
+        assert_eq!(column.present.len(), 3);
+    }
+}
@@ -1,4 +1,14 @@
-// Filename struct to represent a filename with start index and end in byte array
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::hash::{BuildHasher, RandomState};
+
+use crate::element_store::ElementStore;
+use crate::memory::{MemoryUsage, hash_map_bytes, vec_bytes};
+
+// Filename struct to represent a filename with start index and end in byte array.
+// Elements with the same interned name share the Filename, so it can serve as an id of the name.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct Filename(usize, usize);
 impl Filename {
     pub fn new(start: usize, end: usize) -> Self {
@@ -7,57 +17,176 @@ impl Filename {
     pub fn len(&self) -> usize {
         self.1 - self.0
     }
+    pub fn is_empty(&self) -> bool {
+        self.0 == self.1
+    }
+    // Start and end of the filename in the string buffer
+    pub fn bounds(&self) -> (usize, usize) {
+        (self.0, self.1)
+    }
 }
 
+// Filenames already in the string buffer by the hash of the name, so repeated names like
+// index.js or desktop.ini are stored once. Names with colliding hashes are simply not shared.
+struct Interner {
+    hasher: RandomState,
+    names: HashMap<u64, Filename>,
+}
+
+// Integer type of element ids. 32 bits halve the memory of parents, children, postings and sort
+// orders compared to usize, the wide_ids feature allows more than u32::MAX elements.
+#[cfg(not(feature = "wide_ids"))]
+pub type RawId = u32;
+#[cfg(feature = "wide_ids")]
+pub type RawId = u64;
+
+// Index of an element in the tree
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ElementId(RawId);
+impl ElementId {
+    pub const ROOT: ElementId = ElementId(0);
+
+    pub fn new(index: usize) -> Self {
+        match RawId::try_from(index) {
+            Ok(id) => ElementId(id),
+            Err(_) => panic!("element index {} does not fit into an id, enable the wide_ids feature", index),
+        }
+    }
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+impl fmt::Display for ElementId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+// Windows attribute bit marking directories
+pub const FILE_ATTRIBUTE_DIRECTORY: u32 = 0x10;
+
+// An element to add to the tree. The tree may store its fields in columns, see FileTree::freeze.
 pub struct Element {
     pub filename: Filename,
     pub size: Option<i64>,
     pub date_modified: Option<i64>,
     pub date_created: Option<i64>,
     pub attributes: u32,
-    pub parent: usize,
-    pub children: Vec<usize>,
+    pub parent: ElementId,
+    pub children: Vec<ElementId>,
+}
+
+// An element of the tree, reading its fields from the tree's storage
+#[derive(Clone, Copy)]
+pub struct ElementRef<'a> {
+    tree: &'a FileTree,
+    index: ElementId,
+}
+impl<'a> ElementRef<'a> {
+    pub fn id(&self) -> ElementId {
+        self.index
+    }
+    pub fn filename(&self) -> Filename {
+        self.tree.elements.filename(self.index)
+    }
+    // The filename as a string, resolved in the tree's name buffer
+    pub fn name(&self) -> &'a str {
+        self.tree.filename_as_str(&self.filename())
+    }
+    pub fn size(&self) -> Option<i64> {
+        self.tree.elements.size(self.index)
+    }
+    pub fn date_modified(&self) -> Option<i64> {
+        self.tree.elements.date_modified(self.index)
+    }
+    pub fn date_created(&self) -> Option<i64> {
+        self.tree.elements.date_created(self.index)
+    }
+    pub fn attributes(&self) -> u32 {
+        self.tree.elements.attributes(self.index)
+    }
+    pub fn parent(&self) -> ElementId {
+        self.tree.elements.parent(self.index)
+    }
+    pub fn children(&self) -> &'a [ElementId] {
+        self.tree.elements.children(self.index)
+    }
+    pub fn is_folder(&self) -> bool {
+        // Folders only created as parents of other entries have no attributes set
+        self.attributes() & FILE_ATTRIBUTE_DIRECTORY != 0 || !self.children().is_empty()
+    }
 }
-impl Element {}
 
 pub struct FileTree {
-    pub elements: Vec<Element>,
+    // Element structs while building, columns once frozen
+    elements: ElementStore,
     strbuf: Vec<u8>, // Buffer for storing filenames as byte arrays
+    // Pre-order numbering of the elements, see compute_intervals. Empty while not computed or outdated.
+    preorder_entry: Vec<RawId>, // Position of each element in pre-order
+    preorder_exit: Vec<RawId>,  // Position after the last descendant of each element
+    preorder: Vec<ElementId>,   // Elements in pre-order
+    // Tombstones of removed elements, their slots are kept so indices stay valid. Empty until something is removed.
+    removed: Vec<bool>,
+    // Only while loading, see start_interning
+    interner: Option<Interner>,
 }
 impl FileTree {
     pub fn with_capacity(capacity: usize) -> Self {
         // create a new FileTree with a specified initial capacity and a root element
         let mut tree = FileTree {
-            elements: Vec::with_capacity(capacity),
+            elements: ElementStore::Elements(Vec::with_capacity(capacity)),
             strbuf: Vec::with_capacity(capacity * 10), // Initial capacity for the string buffer
+            preorder_entry: Vec::new(),
+            preorder_exit: Vec::new(),
+            preorder: Vec::new(),
+            removed: Vec::new(),
+            interner: None,
         };
         // Add a root element
         tree.add_root();
         tree
     }
 
-    pub fn add_element(&mut self, element: Element) -> usize {
-        let index = self.elements.len();
+    pub(crate) fn from_parts(elements: Vec<Element>, strbuf: Vec<u8>) -> Self {
+        // Reassemble a tree from its elements and string buffer, used when loading a snapshot.
+        // The caller has to ensure that every filename is a valid UTF-8 range of the buffer.
+        FileTree {
+            elements: ElementStore::Elements(elements),
+            strbuf,
+            preorder_entry: Vec::new(),
+            preorder_exit: Vec::new(),
+            preorder: Vec::new(),
+            removed: Vec::new(),
+            interner: None,
+        }
+    }
+    pub(crate) fn strbuf(&self) -> &[u8] {
+        &self.strbuf
+    }
+
+    pub fn add_element(&mut self, element: Element) -> ElementId {
+        self.clear_intervals();
+        let index = ElementId::new(self.elements.len());
         self.elements.push(element);
         index
     }
 
-    fn add_root(&mut self) -> usize {
+    fn add_root(&mut self) -> ElementId {
         // Add a root element if it doesn't exist
-        if self.elements.is_empty() {
+        if self.elements.len() == 0 {
             let filename = self.new_filename("Root");
             let root = Element {
-                filename: filename,
+                filename,
                 size: None,
                 date_modified: None,
                 date_created: None,
                 attributes: 0,
-                parent: 0, // Root has no parent
+                parent: ElementId::ROOT, // Root has no parent
                 children: Vec::new(),
             };
             self.add_element(root)
         } else {
-            0 // Return the index of the existing root element
+            ElementId::ROOT // Return the index of the existing root element
         }
     }
 
@@ -68,19 +197,19 @@ impl FileTree {
         date_modified: Option<i64>,
         date_created: Option<i64>,
         attributes: u32,
-    ) -> usize {
-        let mut current_index = 0; // Start from the root
+    ) -> ElementId {
+        let mut current_index = ElementId::ROOT; // Start from the root
         for part in path.split(&['\\', '/']) {
             // println!("Part: {}, current_index: {}", part, current_index);
             // if part == "tank" { panic!("Debugging"); }
 
             // Check if the part already exists among the children
-            let found_elem = self.elements[current_index]
-                .children
+            let found_elem = self
+                .children(current_index)
                 .binary_search_by_key(&part, |&child_index| self.get_filename(child_index));
             // println!("Found elem: {:?}", found_elem);
             current_index = match found_elem {
-                Ok(index) => self.elements[current_index].children[index], // Move to the existing child
+                Ok(index) => self.children(current_index)[index], // Move to the existing child
                 Err(index) => {
                     // Create a new element
                     let new_element = Element {
@@ -93,42 +222,98 @@ impl FileTree {
                         children: Vec::new(),
                     };
                     let child_index = self.add_element(new_element);
-                    self.elements[current_index]
-                        .children
-                        .insert(index, child_index);
+                    self.elements.children_mut(current_index).insert(index, child_index);
                     child_index
                 }
             };
         }
         // Update the final element with the provided metadata
-        let element = self
-            .elements
-            .get_mut(current_index)
-            .expect("Element should exist");
-        element.size = size;
-        element.date_modified = date_modified;
-        element.date_created = date_created;
-        element.attributes = attributes;
+        self.elements.set_metadata(current_index, size, date_modified, date_created, attributes);
 
         current_index
     }
 
     pub fn new_filename(&mut self, string: &str) -> Filename {
-        // Create a new Filename from a string, storing it in the strbuf
+        // Create a new Filename from a string, storing it in the strbuf unless it is already interned
+        let hash = self.interner.as_ref().map(|interner| interner.hasher.hash_one(string));
+        if let (Some(interner), Some(hash)) = (&self.interner, hash)
+            && let Some(&filename) = interner.names.get(&hash)
+            && self.filename_as_str(&filename) == string
+        {
+            return filename;
+        }
         let start = self.strbuf.len();
         self.strbuf.extend_from_slice(string.as_bytes());
         let end = self.strbuf.len();
-        Filename::new(start, end)
+        let filename = Filename::new(start, end);
+        if let (Some(interner), Some(hash)) = (&mut self.interner, hash) {
+            interner.names.entry(hash).or_insert(filename);
+        }
+        filename
     }
 
-    pub fn get(&self, index: usize) -> Option<&Element> {
-        self.elements.get(index)
+    // Shares the filenames added from now on between all elements with the same name. Meant for
+    // loading, the table of names takes memory until stop_interning.
+    pub fn start_interning(&mut self) {
+        let mut interner = Interner {
+            hasher: RandomState::new(),
+            names: HashMap::new(),
+        };
+        // Names already in the tree can be shared as well
+        for index in self.ids() {
+            let filename = self.elements.filename(index);
+            let hash = interner.hasher.hash_one(self.filename_as_str(&filename));
+            interner.names.entry(hash).or_insert(filename);
+        }
+        self.interner = Some(interner);
+    }
+    pub fn stop_interning(&mut self) {
+        self.interner = None;
     }
-    pub fn get_mut(&mut self, index: usize) -> Option<&mut Element> {
-        self.elements.get_mut(index)
+
+    // Converts the elements into a compact layout once the tree is built: one column per field,
+    // with bitmaps for the missing sizes and dates, and the children of all elements in one array.
+    // The tree can still be changed afterwards, changed children are kept aside until the next freeze.
+    pub fn freeze(&mut self) {
+        self.elements.freeze();
+        self.shrink_to_fit();
+    }
+    pub fn is_frozen(&self) -> bool {
+        self.elements.is_frozen()
+    }
+
+    pub fn get(&self, index: ElementId) -> Option<ElementRef<'_>> {
+        (index.index() < self.elements.len()).then_some(ElementRef { tree: self, index })
     }
-    pub fn get_elements(&self) -> &[Element] {
-        &self.elements
+    // Ids of all elements, including the root and removed ones
+    pub fn ids(&self) -> impl Iterator<Item = ElementId> + use<> {
+        (0..self.elements.len()).map(ElementId::new)
+    }
+
+    // Fields of the element at the specified index, which has to exist
+    pub fn filename(&self, index: ElementId) -> Filename {
+        self.elements.filename(index)
+    }
+    pub fn parent(&self, index: ElementId) -> ElementId {
+        self.elements.parent(index)
+    }
+    pub fn children(&self, index: ElementId) -> &[ElementId] {
+        self.elements.children(index)
+    }
+    pub fn size(&self, index: ElementId) -> Option<i64> {
+        self.elements.size(index)
+    }
+    pub fn date_modified(&self, index: ElementId) -> Option<i64> {
+        self.elements.date_modified(index)
+    }
+    pub fn date_created(&self, index: ElementId) -> Option<i64> {
+        self.elements.date_created(index)
+    }
+    pub fn attributes(&self, index: ElementId) -> u32 {
+        self.elements.attributes(index)
+    }
+    pub fn is_folder(&self, index: ElementId) -> bool {
+        ElementRef { tree: self, index }.is_folder()
     }
 
     pub fn filename_as_str(&self, filename: &Filename) -> &str {
@@ -137,9 +322,9 @@ impl FileTree {
         // Convert bytes to str, assuming UTF-8 encoding
         std::str::from_utf8(filename_bytes).unwrap_or("")
     }
-    pub fn get_filename(&self, index: usize) -> &str {
+    pub fn get_filename(&self, index: ElementId) -> &str {
         // Get the filename of the element at the specified index
-        let filename = &self.elements[index].filename;
+        let filename = self.elements.filename(index);
         // Convert the byte slice to a str using the start and end indices
         // SAFETY: We ensure that the indices are valid when creating Filename instances
         let filename_bytes = unsafe { self.strbuf.get_unchecked(filename.0..filename.1) };
@@ -148,27 +333,42 @@ impl FileTree {
         unsafe { std::str::from_utf8_unchecked(filename_bytes) }
     }
 
-    pub fn get_full_path(&self, index: usize) -> String {
+    pub fn get_full_path(&self, index: ElementId) -> String {
         // Get the path of the element at the specified index. Not including the filename itself.
         let mut path = String::new();
         let mut current_index = index;
-        while current_index != 0 {
-            let element = &self.elements[current_index];
+        while current_index != ElementId::ROOT {
+            let filename = self.get_filename(current_index);
             if !path.is_empty() {
-                path = format!("{}\\{}", self.filename_as_str(&element.filename), path);
+                path = format!("{}\\{}", filename, path);
             } else {
-                path = self.filename_as_str(&element.filename).to_string();
+                path = filename.to_string();
             }
-            current_index = element.parent;
+            current_index = self.parent(current_index);
         }
         path
     }
 
-    pub fn collect_all_children(&self, index: usize) -> Vec<usize> {
+    pub fn find_path(&self, path: &str) -> Option<ElementId> {
+        // Find the element at the given path, accepting both \ and / and ignoring case. The empty path is the root.
+        let mut current_index = ElementId::ROOT;
+        for part in path.split(&['\\', '/']).filter(|part| !part.is_empty()) {
+            let children = self.children(current_index);
+            current_index = match children.binary_search_by_key(&part, |&child| self.get_filename(child)) {
+                Ok(index) => children[index],
+                Err(_) => *children
+                    .iter()
+                    .find(|&&child| self.get_filename(child).to_lowercase() == part.to_lowercase())?,
+            };
+        }
+        Some(current_index)
+    }
+
+    pub fn collect_all_children(&self, index: ElementId) -> Vec<ElementId> {
         // Collect all children of the specified element recursively
         let mut children = Vec::new();
         if let Some(element) = self.get(index) {
-            for &child_index in &element.children {
+            for &child_index in element.children() {
                 children.push(child_index);
                 children.extend(self.collect_all_children(child_index));
             }
@@ -176,20 +376,387 @@ impl FileTree {
         children
     }
 
-    pub fn add_child(&mut self, parent: usize, mut child: Element) -> usize {
+    pub fn compute_intervals(&mut self) {
+        // Number the elements in pre-order, so the descendants of an element are the contiguous
+        // range between its entry and exit position
+        let mut entry: Vec<RawId> = vec![0; self.elements.len()];
+        let mut exit: Vec<RawId> = vec![0; self.elements.len()];
+        let mut preorder = Vec::with_capacity(self.elements.len());
+        // Iterative depth-first search, the flag marks the second visit after all children
+        let mut stack = vec![(ElementId::ROOT, false)];
+        while let Some((index, children_done)) = stack.pop() {
+            if children_done {
+                exit[index.index()] = preorder.len() as RawId;
+                continue;
+            }
+            entry[index.index()] = preorder.len() as RawId;
+            preorder.push(index);
+            stack.push((index, true));
+            stack.extend(self.children(index).iter().rev().map(|&child| (child, false)));
+        }
+        self.preorder_entry = entry;
+        self.preorder_exit = exit;
+        self.preorder = preorder;
+    }
+
+    fn clear_intervals(&mut self) {
+        self.preorder_entry.clear();
+        self.preorder_exit.clear();
+        self.preorder.clear();
+    }
+
+    pub fn has_intervals(&self) -> bool {
+        !self.preorder.is_empty()
+    }
+
+    pub fn is_descendant(&self, index: ElementId, ancestor: ElementId) -> bool {
+        // Check if the element is below the ancestor, in O(1) once the intervals are computed
+        if self.has_intervals() {
+            let entry = self.preorder_entry[index.index()];
+            return self.preorder_entry[ancestor.index()] < entry && entry < self.preorder_exit[ancestor.index()];
+        }
+        let mut current_index = index;
+        while current_index != ElementId::ROOT {
+            current_index = self.parent(current_index);
+            if current_index == ancestor {
+                return true;
+            }
+        }
+        false
+    }
+
+    pub fn descendants(&self, index: ElementId) -> Option<&[ElementId]> {
+        // All elements below the specified element in pre-order, None while the intervals are not computed
+        if !self.has_intervals() {
+            return None;
+        }
+        let entry = self.preorder_entry[index.index()] as usize;
+        let exit = self.preorder_exit[index.index()] as usize;
+        Some(&self.preorder[entry + 1..exit])
+    }
+
+    pub fn is_removed(&self, index: ElementId) -> bool {
+        self.removed.get(index.index()).copied().unwrap_or(false)
+    }
+    pub fn has_removed(&self) -> bool {
+        !self.removed.is_empty()
+    }
+    pub(crate) fn mark_removed(&mut self, index: ElementId) {
+        if self.removed.len() < self.elements.len() {
+            self.removed.resize(self.elements.len(), false);
+        }
+        self.removed[index.index()] = true;
+    }
+
+    pub fn remove_recursive(&mut self, index: ElementId) -> Result<Vec<ElementId>, Box<dyn Error>> {
+        // Remove the element and everything below it. The slots become tombstones that are never
+        // reused, so other indices stay valid. Returns the removed elements.
+        self.check_movable(index)?;
+        let parent = self.parent(index);
+        self.elements.children_mut(parent).retain(|&child| child != index);
+        let mut removed = vec![index];
+        removed.extend(self.collect_all_children(index));
+        for &removed_index in &removed {
+            self.mark_removed(removed_index);
+        }
+        self.clear_intervals();
+        Ok(removed)
+    }
+
+    pub fn rename(&mut self, index: ElementId, new_name: &str) -> Result<Vec<ElementId>, Box<dyn Error>> {
+        // Rename the element, keeping its siblings sorted. Returns the element followed by all
+        // elements below it, whose paths changed.
+        self.check_movable(index)?;
+        if new_name.is_empty() || new_name.contains(['\\', '/']) {
+            return Err(format!("invalid filename '{}'", new_name).into());
+        }
+        let parent = self.parent(index);
+        self.check_name_free(parent, index, new_name)?;
+        self.elements.children_mut(parent).retain(|&child| child != index);
+        let filename = self.new_filename(new_name);
+        self.elements.set_filename(index, filename);
+        self.insert_child_sorted(parent, index);
+        Ok(self.with_descendants(index))
+    }
+
+    pub fn move_to(
+        &mut self,
+        index: ElementId,
+        new_parent: ElementId,
+    ) -> Result<Vec<ElementId>, Box<dyn Error>> {
+        // Move the element into another folder, keeping the children sorted. Returns the element
+        // followed by all elements below it, whose paths changed.
+        self.check_movable(index)?;
+        if self.is_removed(new_parent) || new_parent.index() >= self.elements.len() {
+            return Err(format!("target folder {} does not exist", new_parent).into());
+        }
+        if !self.is_folder(new_parent) {
+            return Err(format!("'{}' is not a folder", self.get_full_path(new_parent)).into());
+        }
+        if new_parent == index || self.is_descendant(new_parent, index) {
+            return Err("cannot move a folder into itself".into());
+        }
+        let name = self.get_filename(index).to_string();
+        self.check_name_free(new_parent, index, &name)?;
+        let parent = self.parent(index);
+        self.elements.children_mut(parent).retain(|&child| child != index);
+        self.elements.set_parent(index, new_parent);
+        self.insert_child_sorted(new_parent, index);
+        self.clear_intervals();
+        Ok(self.with_descendants(index))
+    }
+
+    fn check_movable(&self, index: ElementId) -> Result<(), Box<dyn Error>> {
+        if index == ElementId::ROOT {
+            return Err("the root element cannot be changed".into());
+        }
+        if index.index() >= self.elements.len() || self.is_removed(index) {
+            return Err(format!("element {} does not exist", index).into());
+        }
+        Ok(())
+    }
+
+    fn check_name_free(&self, parent: ElementId, index: ElementId, name: &str) -> Result<(), Box<dyn Error>> {
+        // Another child of the parent with the same name would make the path ambiguous. Names are
+        // compared ignoring case, like find_path resolves them.
+        let lower_name = name.to_lowercase();
+        let taken = self.children(parent).iter().any(|&child| {
+            child != index && self.get_filename(child).to_lowercase() == lower_name
+        });
+        if taken {
+            return Err(format!("'{}' already exists in {}", name, self.get_full_path(parent)).into());
+        }
+        Ok(())
+    }
+
+    fn insert_child_sorted(&mut self, parent: ElementId, child: ElementId) {
+        let name = self.get_filename(child);
+        let position = match self.children(parent).binary_search_by_key(&name, |&other| self.get_filename(other)) {
+            Ok(position) | Err(position) => position,
+        };
+        self.elements.children_mut(parent).insert(position, child);
+    }
+
+    fn with_descendants(&self, index: ElementId) -> Vec<ElementId> {
+        let mut indices = vec![index];
+        indices.extend(self.collect_all_children(index));
+        indices
+    }
+
+    pub fn add_child(&mut self, parent: ElementId, mut child: Element) -> ElementId {
         // Add a child element to the specified parent element
-        let child_index = self.elements.len();
-        self.elements[parent].children.push(child_index);
+        self.clear_intervals();
+        let child_index = ElementId::new(self.elements.len());
+        self.elements.children_mut(parent).push(child_index);
         child.parent = parent;
         self.elements.push(child);
         child_index
     }
     pub fn shrink_to_fit(&mut self) {
-        // Reduce the capacity of the elements vector to fit the current number of elements
+        // Reduce the capacity of the elements to fit the current number of elements
         self.elements.shrink_to_fit();
+        self.strbuf.shrink_to_fit();
     }
     pub fn len(&self) -> usize {
         // Return the number of elements in the tree
         self.elements.len()
     }
+    pub fn is_empty(&self) -> bool {
+        self.elements.len() == 0
+    }
+
+    pub fn memory_usage(&self) -> MemoryUsage {
+        MemoryUsage::new("file_tree")
+            .with("strbuf", self.strbuf.capacity())
+            .with_part(self.elements.memory_usage())
+            .with(
+                "intervals",
+                vec_bytes(&self.preorder_entry) + vec_bytes(&self.preorder_exit) + vec_bytes(&self.preorder),
+            )
+            .with("tombstones", vec_bytes(&self.removed))
+            .with("interner", self.interner.as_ref().map_or(0, |interner| hash_map_bytes(&interner.names)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_element_id() {
+        let id = ElementId::new(RawId::MAX as usize);
+        assert_eq!(id.index(), RawId::MAX as usize);
+        assert!(ElementId::ROOT < id);
+        assert_eq!(std::mem::size_of::<Option<ElementId>>(), 2 * std::mem::size_of::<RawId>());
+        #[cfg(not(feature = "wide_ids"))]
+        assert!(std::panic::catch_unwind(|| ElementId::new(u32::MAX as usize + 1)).is_err());
+    }
+
+    #[test]
+    fn test_interning() {
+        let mut tree = FileTree::with_capacity(10);
+        let first = tree.add_or_update_recursive("a\\index.js", None, None, None, 0);
+        tree.start_interning();
+        let second = tree.add_or_update_recursive("b\\index.js", None, None, None, 0);
+        let third = tree.add_or_update_recursive("b\\a\\index.js", None, None, None, 0);
+        let len = tree.strbuf().len();
+        let filename = |index: ElementId| tree.filename(index);
+        // Names added before interning started are shared too
+        assert_eq!(filename(first), filename(second));
+        assert_eq!(filename(second), filename(third));
+        assert_eq!(len, "Rootaindex.jsb".len());
+        assert_eq!(tree.get_full_path(third), "b\\a\\index.js");
+        assert!(tree.memory_usage().get("interner").unwrap().bytes > 0);
+
+        let shared = filename(first);
+
+        tree.stop_interning();
+        let fourth = tree.add_or_update_recursive("c\\index.js", None, None, None, 0);
+        assert_ne!(tree.filename(fourth), shared);
+        // Renaming an element does not change others sharing its name
+        tree.rename(second, "main.js").unwrap();
+        assert_eq!(tree.get_filename(first), "index.js");
+        assert_eq!(tree.get_filename(second), "main.js");
+    }
+
+    #[test]
+    fn test_freeze() {
+        let build = || {
+            let mut tree = FileTree::with_capacity(10);
+            tree.add_or_update_recursive("C:\\docs\\report.pdf", Some(1000), Some(5), None, 0x20);
+            tree.add_or_update_recursive("C:\\docs\\notes.txt", None, Some(7), Some(3), 0);
+            tree.add_or_update_recursive("C:\\music", None, None, None, FILE_ATTRIBUTE_DIRECTORY);
+            tree
+        };
+        let assert_same = |a: &FileTree, b: &FileTree| {
+            assert_eq!(a.len(), b.len());
+            for index in a.ids() {
+                assert_eq!(a.get_full_path(index), b.get_full_path(index));
+                assert_eq!((a.size(index), a.date_modified(index)), (b.size(index), b.date_modified(index)));
+                assert_eq!(a.date_created(index), b.date_created(index));
+                assert_eq!(a.attributes(index), b.attributes(index));
+                assert_eq!((a.parent(index), a.children(index)), (b.parent(index), b.children(index)));
+                assert_eq!(a.is_folder(index), b.is_folder(index));
+            }
+        };
+        let mut tree = build();
+        let mut frozen = build();
+        frozen.freeze();
+        assert!(frozen.is_frozen() && !tree.is_frozen());
+        assert_same(&tree, &frozen);
+        let report = frozen.find_path("c:\\docs\\report.pdf").unwrap();
+        assert_eq!(frozen.get(report).unwrap().size(), Some(1000));
+        assert!(frozen.get(ElementId::new(frozen.len())).is_none());
+        let usage = |tree: &FileTree| tree.memory_usage().get("elements").unwrap().bytes;
+        assert!(usage(&frozen) < usage(&tree));
+
+        // A frozen tree can still be changed
+        for tree in [&mut tree, &mut frozen] {
+            let music = tree.find_path("C:\\music").unwrap();
+            tree.add_or_update_recursive("C:\\music\\song.mp3", Some(3), None, None, 0);
+            tree.add_or_update_recursive("C:\\docs\\notes.txt", Some(20), None, Some(4), 0);
+            tree.rename(report, "a.pdf").unwrap();
+            tree.move_to(report, music).unwrap();
+            let docs = tree.find_path("C:\\docs").unwrap();
+            tree.remove_recursive(docs).unwrap();
+        }
+        assert_same(&tree, &frozen);
+        assert!(frozen.memory_usage().get("changed_children").unwrap().bytes > 0);
+        // Freezing again moves the changed children back into the rows
+        frozen.freeze();
+        assert_same(&tree, &frozen);
+        assert_eq!(frozen.memory_usage().get("changed_children").unwrap().bytes, 0);
+    }
+
+    #[test]
+    fn test_intervals() {
+        let mut tree = FileTree::with_capacity(10);
+        let file = tree.add_or_update_recursive("C:\\docs\\work\\report.pdf", None, None, None, 0);
+        let work = tree.parent(file);
+        let docs = tree.parent(work);
+        let other = tree.add_or_update_recursive("C:\\music\\song.mp3", None, None, None, 0);
+        let notes = tree.add_or_update_recursive("C:\\docs\\notes.txt", None, None, None, 0);
+        assert!(!tree.has_intervals());
+
+        // Parent walk and intervals give the same answers
+        for computed in [false, true] {
+            if computed {
+                tree.compute_intervals();
+                assert!(tree.has_intervals());
+            }
+            assert!(tree.is_descendant(file, docs));
+            assert!(tree.is_descendant(notes, docs));
+            assert!(tree.is_descendant(file, ElementId::ROOT));
+            assert!(!tree.is_descendant(other, docs));
+            assert!(!tree.is_descendant(docs, docs));
+            assert!(!tree.is_descendant(docs, file));
+        }
+
+        let mut descendants = tree.descendants(docs).unwrap().to_vec();
+        descendants.sort_unstable();
+        let mut expected = tree.collect_all_children(docs);
+        expected.sort_unstable();
+        assert_eq!(descendants, expected);
+        assert_eq!(tree.descendants(ElementId::ROOT).unwrap().len(), tree.len() - 1);
+
+        // Adding elements invalidates the intervals
+        tree.add_or_update_recursive("C:\\docs\\new.txt", None, None, None, 0);
+        assert!(!tree.has_intervals());
+        assert_eq!(tree.descendants(docs), None);
+    }
+
+    #[test]
+    fn test_remove_rename_move() {
+        let mut tree = FileTree::with_capacity(10);
+        let report = tree.add_or_update_recursive("docs\\work\\report.pdf", None, None, None, 0);
+        let work = tree.parent(report);
+        let docs = tree.parent(work);
+        let notes = tree.add_or_update_recursive("docs\\notes.txt", None, None, None, 0);
+        let music = tree.add_or_update_recursive("music", None, None, None, 0x10);
+        let sorted_names = |tree: &FileTree, index: ElementId| -> Vec<String> {
+            let children = tree.children(index);
+            children.iter().map(|&child| tree.get_filename(child).to_string()).collect()
+        };
+
+        // Renaming keeps the children sorted and reports the paths that changed
+        assert_eq!(tree.rename(work, "archive").unwrap(), vec![work, report]);
+        assert_eq!(sorted_names(&tree, docs), vec!["archive", "notes.txt"]);
+        assert_eq!(tree.rename(notes, "zeta.txt").unwrap(), vec![notes]);
+        assert_eq!(sorted_names(&tree, docs), vec!["archive", "zeta.txt"]);
+        assert_eq!(tree.find_path("docs\\archive\\report.pdf"), Some(report));
+        assert!(tree.rename(notes, "archive").is_err());
+        assert!(tree.rename(notes, "Archive").is_err());
+        assert_eq!(tree.rename(work, "Archive").unwrap(), vec![work, report]);
+        assert_eq!(tree.rename(work, "archive").unwrap(), vec![work, report]);
+        assert!(tree.rename(notes, "a/b").is_err());
+        assert!(tree.rename(ElementId::ROOT, "x").is_err());
+
+        // Moving into a subfolder of itself, into a file or next to a name differing in case is refused
+        assert!(tree.move_to(docs, work).is_err());
+        assert!(tree.move_to(work, notes).is_err());
+        let other_report = tree.add_or_update_recursive("music\\Report.pdf", None, None, None, 0);
+        assert!(tree.move_to(report, music).is_err());
+        tree.remove_recursive(other_report).unwrap();
+        tree.compute_intervals();
+        assert_eq!(tree.move_to(work, music).unwrap(), vec![work, report]);
+        assert!(!tree.has_intervals());
+        assert_eq!(tree.get_full_path(report), "music\\archive\\report.pdf");
+        assert!(tree.is_descendant(report, music));
+        assert!(!tree.is_descendant(report, docs));
+        assert_eq!(sorted_names(&tree, docs), vec!["zeta.txt"]);
+
+        // Removed elements become tombstones, the other indices stay valid
+        let len = tree.len();
+        let mut removed = tree.remove_recursive(music).unwrap();
+        removed.sort_unstable();
+        assert_eq!(removed, vec![work, report, music]);
+        assert_eq!(tree.len(), len);
+        assert!(tree.is_removed(report) && !tree.is_removed(notes));
+        assert_eq!(tree.find_path("music"), None);
+        assert!(tree.rename(report, "x").is_err());
+        assert!(tree.move_to(notes, music).is_err());
+        assert_eq!(tree.get_full_path(notes), "docs\\zeta.txt");
+        let again = tree.add_or_update_recursive("music", None, None, None, 0x10);
+        assert_ne!(again, music);
+    }
 }
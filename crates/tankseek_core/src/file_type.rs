@@ -0,0 +1,128 @@
+use serde::{Deserialize, Serialize};
+
+use crate::file_tree::ElementRef;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileCategory {
+    Folder,
+    Audio,
+    Video,
+    Picture,
+    Document,
+    Executable,
+    Archive,
+    Other,
+}
+
+// Extension to category table, following the defaults of Everything's search macros
+const EXTENSION_CATEGORIES: &[(FileCategory, &[&str])] = &[
+    (
+        FileCategory::Audio,
+        &[
+            "aac", "ac3", "aif", "aifc", "aiff", "amr", "ape", "au", "cda", "dts", "fla", "flac",
+            "it", "m1a", "m2a", "m3u", "m4a", "mid", "midi", "mka", "mod", "mp2", "mp3", "mpa",
+            "ogg", "opus", "ra", "rmi", "snd", "spc", "umx", "voc", "wav", "wma", "xm",
+        ],
+    ),
+    (
+        FileCategory::Video,
+        &[
+            "3g2", "3gp", "3gp2", "3gpp", "amv", "asf", "avi", "bik", "divx", "drc", "dsm", "evo",
+            "f4v", "flc", "fli", "flic", "flv", "ifo", "ivf", "m1v", "m2p", "m2t", "m2ts", "m2v",
+            "m4b", "m4p", "m4v", "mkv", "mov", "mp2v", "mp4", "mp4v", "mpe", "mpeg", "mpg",
+            "mpv2", "mpv4", "mts", "ogm", "ogv", "qt", "ram", "rm", "rmvb", "swf", "ts", "vob",
+            "webm", "wm", "wmp", "wmv",
+        ],
+    ),
+    (
+        FileCategory::Picture,
+        &[
+            "ani", "avif", "bmp", "gif", "heic", "heif", "ico", "jfif", "jpe", "jpeg", "jpg",
+            "pcx", "png", "psd", "svg", "tga", "tif", "tiff", "webp", "wmf",
+        ],
+    ),
+    (
+        FileCategory::Document,
+        &[
+            "c", "chm", "cpp", "csv", "cxx", "doc", "docm", "docx", "dot", "dotm", "dotx", "epub",
+            "h", "hpp", "htm", "html", "hxx", "ini", "java", "json", "lua", "md", "mht", "mhtml",
+            "odp", "ods", "odt", "pdf", "potm", "potx", "ppam", "pps", "ppsm", "ppsx", "ppt",
+            "pptm", "pptx", "rtf", "sldm", "sldx", "thmx", "txt", "vsd", "wpd", "wps", "wri",
+            "xlam", "xls", "xlsb", "xlsm", "xlsx", "xltm", "xltx", "xml",
+        ],
+    ),
+    (
+        FileCategory::Executable,
+        &["bat", "cmd", "com", "exe", "msi", "msp", "ps1", "scr"],
+    ),
+    (
+        FileCategory::Archive,
+        &[
+            "7z", "ace", "arj", "bz2", "cab", "gz", "gzip", "jar", "lz", "lzma", "rar", "tar",
+            "tgz", "txz", "xz", "z", "zip", "zst",
+        ],
+    ),
+];
+
+impl FileCategory {
+    // Category of a search macro like audio: or pic:
+    pub fn from_macro(name: &str) -> Option<Self> {
+        match name {
+            "audio" => Some(FileCategory::Audio),
+            "video" => Some(FileCategory::Video),
+            "pic" => Some(FileCategory::Picture),
+            "doc" => Some(FileCategory::Document),
+            "exe" => Some(FileCategory::Executable),
+            "zip" => Some(FileCategory::Archive),
+            _ => None,
+        }
+    }
+
+    pub fn from_extension(ext: &str) -> Self {
+        let ext = ext.to_lowercase();
+        EXTENSION_CATEGORIES
+            .iter()
+            .find(|(_, extensions)| extensions.contains(&ext.as_str()))
+            .map_or(FileCategory::Other, |&(category, _)| category)
+    }
+
+    pub fn from_filename(filename: &str) -> Self {
+        match filename.rsplit_once('.') {
+            Some((_, ext)) => FileCategory::from_extension(ext),
+            None => FileCategory::Other,
+        }
+    }
+
+    pub fn of_element(element: ElementRef<'_>) -> Self {
+        if element.is_folder() {
+            FileCategory::Folder
+        } else {
+            FileCategory::from_filename(element.name())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_tree::FileTree;
+
+    #[test]
+    fn test_categories() {
+        assert_eq!(FileCategory::from_filename("song.MP3"), FileCategory::Audio);
+        assert_eq!(FileCategory::from_filename("movie.mkv"), FileCategory::Video);
+        assert_eq!(FileCategory::from_filename("photo.jpeg"), FileCategory::Picture);
+        assert_eq!(FileCategory::from_filename("notes.txt"), FileCategory::Document);
+        assert_eq!(FileCategory::from_filename("setup.exe"), FileCategory::Executable);
+        assert_eq!(FileCategory::from_filename("backup.tar.gz"), FileCategory::Archive);
+        assert_eq!(FileCategory::from_filename("Makefile"), FileCategory::Other);
+        assert_eq!(FileCategory::from_filename("data.unknown"), FileCategory::Other);
+
+        let mut tree = FileTree::with_capacity(3);
+        let file = tree.add_or_update_recursive("music/song.mp3", Some(100), None, None, 0);
+        let folder = tree.parent(file);
+        assert_eq!(FileCategory::of_element(tree.get(folder).unwrap()), FileCategory::Folder);
+        assert_eq!(FileCategory::of_element(tree.get(file).unwrap()), FileCategory::Audio);
+    }
+}
@@ -1,29 +1,48 @@
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::hash::{BuildHasher, Hash, RandomState};
 
-use crate::file_tree::FileTree;
+use crate::file_tree::{ElementId, FileTree, Filename};
+use crate::indexer::delta_segment::{DeltaSegment, merged_postings};
+use crate::indexer::postings::Postings;
+use crate::memory::{MemoryUsage, hash_map_bytes};
 
-#[derive(Hash, Eq, PartialEq, Debug, Clone, PartialOrd, Ord)]
+#[derive(Hash, Eq, PartialEq, Debug, Clone, Copy, PartialOrd, Ord)]
 pub struct Bigram {
     pub first: char,
     pub second: char,
 }
 
+// Number of postings between two skip pointers
+pub const SKIP_INTERVAL: usize = 128;
+// Above this size ratio intersections gallop through the longer list instead of merging
+const GALLOP_RATIO: usize = 8;
+
+#[derive(Clone)]
 pub struct CompressedPostingsList {
     pub indices: Vec<u8>,
     pub length: usize,
+    // Skip pointers at the start of every block of SKIP_INTERVAL postings: the value before the
+    // block, which its first gap is relative to, and the byte offset of the block
+    pub skips: Vec<(usize, usize)>,
 }
 // This struct is used to store the compressed postings list
 // It stores the gaps between the indices
 // Uses variable byte codes https://nlp.stanford.edu/IR-book/html/htmledition/variable-byte-codes-1.html
 impl CompressedPostingsList {
-    pub fn new(postings_list: Vec<usize>) -> Self {
+    pub fn new(postings_list: Vec<ElementId>) -> Self {
         let mut compressed_list = Vec::with_capacity(postings_list.len() * 2);
         let mut last_i = 0;
         let mut bytes: [u8; 10] = [0; 10]; // Buffer for variable byte encoding
         let mut bytes_index = 0; // Index for the bytes buffer
-        for &i in &postings_list {
-            let gap = i as usize - last_i as usize; // Calculate the gap
-            last_i = i; // Update the last index
+        let mut skips = Vec::with_capacity(postings_list.len() / SKIP_INTERVAL + 1);
+        for (k, &i) in postings_list.iter().enumerate() {
+            if k % SKIP_INTERVAL == 0 {
+                skips.push((last_i, compressed_list.len()));
+            }
+            let gap = i.index() - last_i; // Calculate the gap
+            last_i = i.index(); // Update the last index
 
             // Encode the gap using variable byte encoding
             let mut value = gap;
@@ -46,9 +65,36 @@ impl CompressedPostingsList {
         CompressedPostingsList {
             indices: compressed_list,
             length: postings_list.len(),
+            skips,
+        }
+    }
+
+    // Restores a list from its encoded bytes, e.g. read from a snapshot, recreating the skip pointers
+    pub fn from_encoded(indices: Vec<u8>, length: usize) -> Self {
+        let mut skips = Vec::with_capacity(length / SKIP_INTERVAL + 1);
+        let mut last_value = 0;
+        let mut current_value = 0;
+        let mut count = 0;
+        let mut block_start = true;
+        for (offset, &byte) in indices.iter().enumerate() {
+            if block_start && count % SKIP_INTERVAL == 0 {
+                skips.push((last_value, offset));
+            }
+            block_start = byte >= 128;
+            current_value = (current_value << 7) | (byte & 0x7F) as usize;
+            if byte >= 128 {
+                last_value += current_value;
+                current_value = 0;
+                count += 1;
+            }
+        }
+        CompressedPostingsList {
+            indices,
+            length,
+            skips,
         }
     }
-    pub fn decompress(&self) -> Vec<usize> {
+    pub fn decompress(&self) -> Vec<ElementId> {
         let mut postings_list = Vec::with_capacity(self.length);
         let mut last_value = 0; // Last value to calculate gaps
         let mut current_value = 0;
@@ -58,31 +104,255 @@ impl CompressedPostingsList {
             } else {
                 current_value = (current_value << 7) | (byte & 0x7F) as usize; // Add the byte without the continuation bit
                 last_value += current_value; // Update the last value
-                postings_list.push(last_value); // Add the decompressed index
+                postings_list.push(ElementId::new(last_value)); // Add the decompressed index
                 current_value = 0; // Reset for the next value
             }
         }
         postings_list
     }
+
+    // Approximate heap size in bytes
+    pub fn memory_usage(&self) -> usize {
+        self.indices.capacity() + self.skips.capacity() * std::mem::size_of::<(usize, usize)>()
+    }
+
+    // Iterates over the postings, decoding one block at a time
+    pub fn iter(&self) -> impl Iterator<Item = ElementId> + '_ {
+        (0..self.skips.len()).flat_map(|block| self.decode_block(block))
+    }
+
+    // Decodes the postings of one block between two skip pointers
+    fn decode_block(&self, block: usize) -> Vec<ElementId> {
+        let (mut last_value, offset) = self.skips[block];
+        let len = SKIP_INTERVAL.min(self.length - block * SKIP_INTERVAL);
+        let mut postings = Vec::with_capacity(len);
+        let mut current_value = 0;
+        for &byte in &self.indices[offset..] {
+            current_value = (current_value << 7) | (byte & 0x7F) as usize;
+            if byte >= 128 {
+                last_value += current_value;
+                postings.push(ElementId::new(last_value));
+                current_value = 0;
+                if postings.len() == len {
+                    break;
+                }
+            }
+        }
+        postings
+    }
+
+    // The sorted candidates that are in this list. Only the blocks the candidates fall into are
+    // decoded, found by galloping over the skip pointers.
+    pub fn intersect_sorted(&self, candidates: &[ElementId]) -> Vec<ElementId> {
+        self.filter_sorted(candidates, true)
+    }
+    // The sorted candidates that are not in this list, decoding blocks like intersect_sorted
+    pub fn difference_sorted(&self, candidates: &[ElementId]) -> Vec<ElementId> {
+        self.filter_sorted(candidates, false)
+    }
+
+    // Keeps the candidates whose presence in the list equals contained
+    fn filter_sorted(&self, candidates: &[ElementId], contained: bool) -> Vec<ElementId> {
+        if candidates.len() * GALLOP_RATIO > self.length {
+            // Many candidates, merge them with the whole list
+            let mut list = self.iter().peekable();
+            let mut result = Vec::with_capacity(candidates.len());
+            for &candidate in candidates {
+                while list.next_if(|&index| index < candidate).is_some() {}
+                if (list.peek() == Some(&candidate)) == contained {
+                    result.push(candidate);
+                }
+            }
+            return result;
+        }
+        let mut result = Vec::new();
+        let mut block = usize::MAX;
+        let mut decoded = Vec::new();
+        let mut position = 0;
+        let mut next_block = 0;
+        for &candidate in candidates {
+            // The last block starting before the candidate, blocks only move forward
+            next_block = gallop(&self.skips, next_block, |&(before, _)| before < candidate.index());
+            let candidate_block = next_block.saturating_sub(1);
+            if candidate_block != block {
+                block = candidate_block;
+                decoded = self.decode_block(block);
+                position = 0;
+            }
+            position = gallop(&decoded, position, |&index| index < candidate);
+            if (decoded.get(position) == Some(&candidate)) == contained {
+                result.push(candidate);
+            }
+        }
+        result
+    }
+}
+
+// Intersects two sorted lists. Galloping search through the longer list skips most of it when the
+// lengths differ a lot, otherwise both are merged linearly.
+pub fn intersect_sorted(a: &[ElementId], b: &[ElementId]) -> Vec<ElementId> {
+    let (short, long) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+    let mut result = Vec::with_capacity(short.len());
+    if short.len() * GALLOP_RATIO < long.len() {
+        let mut position = 0;
+        for &index in short {
+            position = gallop(long, position, |&other| other < index);
+            if long.get(position) == Some(&index) {
+                result.push(index);
+            }
+        }
+    } else {
+        let (mut i, mut j) = (0, 0);
+        while i < short.len() && j < long.len() {
+            match short[i].cmp(&long[j]) {
+                std::cmp::Ordering::Less => i += 1,
+                std::cmp::Ordering::Greater => j += 1,
+                std::cmp::Ordering::Equal => {
+                    result.push(short[i]);
+                    i += 1;
+                    j += 1;
+                }
+            }
+        }
+    }
+    result
+}
+
+// Like partition_point on slice[start..], but probes exponentially growing steps from start first,
+// which is faster when the partition point is close to start
+fn gallop<T, F: Fn(&T) -> bool>(slice: &[T], start: usize, pred: F) -> usize {
+    let mut step = 1;
+    let mut low = start;
+    while low + step < slice.len() && pred(&slice[low + step]) {
+        low += step;
+        step *= 2;
+    }
+    let high = (low + step + 1).min(slice.len());
+    if low >= slice.len() || !pred(&slice[low]) {
+        return low.min(slice.len());
+    }
+    low + 1 + slice[low + 1..high].partition_point(pred)
 }
 
 pub struct BigramIndex {
-    pub index: HashMap<Bigram, CompressedPostingsList>,
+    // Sparse postings are stored as variable byte gaps, dense ones as bitmaps
+    pub index: HashMap<Bigram, Postings>,
+    // Postings of every character, so single character queries don't have to combine bigrams.
+    // Also covers filenames of a single character, which have no bigrams.
+    pub unigrams: HashMap<char, Postings>,
+    // Elements added since the index was built, see add_elements
+    delta: DeltaSegment<Bigram>,
+    unigram_delta: DeltaSegment<char>,
     num_elements: usize,
 }
 impl BigramIndex {
     pub fn new(tree: &FileTree) -> Self {
-        let index = create_bigram_reverse_index(tree);
+        Self::with_threads(tree, default_threads())
+    }
+    // Builds the index with the given number of threads, each indexing a range of the elements
+    pub fn with_threads(tree: &FileTree, threads: usize) -> Self {
+        let (index, unigrams) = create_bigram_reverse_index(tree, threads);
+        Self::from_parts(index, unigrams, tree.len())
+    }
+    pub(crate) fn from_parts(
+        index: HashMap<Bigram, Postings>,
+        unigrams: HashMap<char, Postings>,
+        num_elements: usize,
+    ) -> Self {
         BigramIndex {
             index,
-            num_elements: tree.len(),
+            unigrams,
+            delta: DeltaSegment::new(),
+            unigram_delta: DeltaSegment::new(),
+            num_elements,
         }
     }
+    pub fn num_elements(&self) -> usize {
+        self.num_elements
+    }
+
+    // Indexes the elements added to the tree since the last call into the delta segment,
+    // which is merged into the compressed lists once it grows large enough
+    pub fn add_elements(&mut self, tree: &FileTree) {
+        let new_elements: Vec<ElementId> = (self.num_elements..tree.len()).map(ElementId::new).collect();
+        self.num_elements = self.num_elements.max(tree.len());
+        self.update_elements(tree, &new_elements);
+    }
+
+    // Indexes the current names of the elements, e.g. after a rename. The bigrams of old names
+    // stay in the index, which only yields extra candidates that are filtered out anyway.
+    pub fn update_elements(&mut self, tree: &FileTree, indices: &[ElementId]) {
+        for &i in indices {
+            let chars: Vec<char> = tree.get_filename(i).to_lowercase().chars().collect();
+            for pair in chars.windows(2) {
+                let bigram = Bigram {
+                    first: pair[0],
+                    second: pair[1],
+                };
+                self.delta.add(bigram, i);
+            }
+            for &c in &chars {
+                self.unigram_delta.add(c, i);
+            }
+        }
+        if self.delta.should_merge() || self.unigram_delta.should_merge() {
+            self.merge_delta();
+        }
+    }
+
+    pub fn merge_delta(&mut self) {
+        merge_delta_into(&mut self.index, &mut self.delta, self.num_elements);
+        merge_delta_into(&mut self.unigrams, &mut self.unigram_delta, self.num_elements);
+    }
+    pub fn has_delta(&self) -> bool {
+        !self.delta.is_empty() || !self.unigram_delta.is_empty()
+    }
+    pub fn delta_memory_usage(&self) -> usize {
+        self.delta.memory_usage() + self.unigram_delta.memory_usage()
+    }
+    pub fn memory_usage(&self) -> MemoryUsage {
+        MemoryUsage::new("bigram_index")
+            .with("bigram_postings", postings_memory_usage(&self.index))
+            .with("bigram_table", hash_map_bytes(&self.index))
+            .with("char_postings", postings_memory_usage(&self.unigrams))
+            .with("char_table", hash_map_bytes(&self.unigrams))
+            .with("delta", self.delta_memory_usage())
+    }
+
+    // The postings of the bigram, including those still in the delta segment
+    fn postings(&self, bigram: &Bigram) -> Option<Cow<'_, Postings>> {
+        postings_with_delta(&self.index, &self.delta, bigram, self.num_elements)
+    }
+    fn unigram_postings(&self, c: char) -> Option<Cow<'_, Postings>> {
+        postings_with_delta(&self.unigrams, &self.unigram_delta, &c, self.num_elements)
+    }
+
+    // All postings lists with the delta segment merged in, e.g. for writing them out
+    pub fn postings_lists(&self) -> impl Iterator<Item = (&Bigram, Cow<'_, Postings>)> {
+        let delta_only = self.delta.keys().filter(|bigram| !self.index.contains_key(bigram));
+        self.index
+            .keys()
+            .chain(delta_only)
+            .map(|bigram| (bigram, self.postings(bigram).unwrap()))
+    }
+    pub fn unigram_lists(&self) -> impl Iterator<Item = (char, Cow<'_, Postings>)> {
+        let delta_only = self.unigram_delta.keys().filter(|c| !self.unigrams.contains_key(c));
+        self.unigrams
+            .keys()
+            .chain(delta_only)
+            .map(|&c| (c, self.unigram_postings(c).unwrap()))
+    }
 
-    pub fn query_word<T: AsRef<str>>(&self, word: T) -> Vec<usize> {
+    pub fn query_word<T: AsRef<str>>(&self, word: T) -> Vec<ElementId> {
         // Split the query into bigrams (bi-letters)
         let mut bigrams = Vec::new();
         let chars: Vec<char> = word.as_ref().chars().collect();
+        // Words without bigrams are looked up by their character
+        match chars.as_slice() {
+            [] => return Vec::new(),
+            &[c] => return self.query_char(c),
+            _ => {}
+        }
         for i in 0..chars.len() - 1 {
             // Create a bigram from the current and next character
             let bigram = Bigram {
@@ -92,138 +362,278 @@ impl BigramIndex {
             bigrams.push(bigram);
         }
 
+        bigrams.sort();
         bigrams.dedup();
 
-        // get the vector of indices for the first bigram
-        let mut indices = match self.index.get(&bigrams[0]) {
-            Some(indices) => indices.decompress(),
-            None => {
-                return Vec::new(); // If the first bigram is not found, return an empty vector
+        let mut lists = Vec::with_capacity(bigrams.len());
+        for bigram in &bigrams {
+            match self.postings(bigram) {
+                Some(postings) => lists.push(postings),
+                None => return Vec::new(), // If a bigram is not found, no element can match
             }
-        };
-        // Iterate over the remaining bigrams and filter the indices
-        let mut filtered_indices = Vec::with_capacity(indices.len());
-        for bigram in &bigrams[1..] {
-            if let Some(postings_list) = self.index.get(bigram) {
-                let next_indices = postings_list.decompress();
-                // Only keep indices that are present in both the current indices and the next indices
-                // As both lists are sorted, we can use a two-pointer technique
-                let mut i = 0;
-                let mut j = 0;
-                while i < indices.len() && j < next_indices.len() {
-                    if indices[i] == next_indices[j] {
-                        filtered_indices.push(indices[i]);
-                        i += 1;
-                        j += 1;
-                    } else if indices[i] < next_indices[j] {
-                        i += 1; // Move to the next index in the current indices
-                    } else {
-                        j += 1; // Move to the next index in the next indices
-                    }
+        }
+        // Start with the shortest list, so the fewest ids have to be looked up in the others
+        lists.sort_by_key(|postings| postings.len());
+        let all_bitmaps = lists
+            .iter()
+            .all(|postings| matches!(postings.as_ref(), Postings::Bitmap(_)));
+        let mut indices = match lists[0].as_ref() {
+            Postings::Bitmap(first) if all_bitmaps => {
+                // Only dense lists, intersect them chunk by chunk
+                let mut result = first.clone();
+                for postings in &lists[1..] {
+                    result = postings.intersect(&result);
                 }
-
-                (indices, filtered_indices) = (filtered_indices, indices); // Update indices to the filtered list
-                filtered_indices.clear(); // Clear the filtered indices for the next iteration
-            } else {
-                // If no indices found for the current bigram, return empty results
-                return Vec::new();
+                return result.to_vec();
             }
+            first => first.to_vec(),
+        };
+        for postings in &lists[1..] {
+            if indices.is_empty() {
+                break;
+            }
+            indices = postings.intersect_sorted(&indices);
         }
-        indices.shrink_to_fit(); // Reduce capacity to the actual size
         indices
     }
-    pub fn query_char(&self, c: char) -> Vec<usize> {
-        // go over the index and find all indices that contain the character
-        let mut indices = vec![false; self.num_elements];
-        for (bigram, postings_list) in &self.index {
-            if bigram.first == c || bigram.second == c {
-                let decompressed_indices = postings_list.decompress();
-                for &index in &decompressed_indices {
-                    indices[index] = true; // Mark the index as containing the character
+    // Returns the sorted indices of the elements sharing at least min_shared distinct bigrams with
+    // the word. At most limit of them are returned, those sharing the most bigrams first.
+    pub fn query_overlap<T: AsRef<str>>(&self, word: T, min_shared: usize, limit: usize) -> Vec<ElementId> {
+        let chars: Vec<char> = word.as_ref().chars().collect();
+        let mut bigrams: Vec<Bigram> = chars
+            .windows(2)
+            .map(|pair| Bigram {
+                first: pair[0],
+                second: pair[1],
+            })
+            .collect();
+        bigrams.sort();
+        bigrams.dedup();
+
+        let lists: Vec<Cow<'_, Postings>> = bigrams.iter().filter_map(|bigram| self.postings(bigram)).collect();
+        if lists.len() < min_shared.max(1) {
+            return Vec::new();
+        }
+
+        // Count in how many lists each element is with a k-way merge, grouping the elements by count
+        let mut iters: Vec<_> = lists.iter().map(|postings| postings.iter()).collect();
+        let mut heap: BinaryHeap<Reverse<(ElementId, usize)>> = (0..iters.len())
+            .filter_map(|list| Some(Reverse((iters[list].next()?, list))))
+            .collect();
+        let mut by_count = vec![Vec::new(); lists.len() + 1];
+        while let Some(Reverse((index, list))) = heap.pop() {
+            // Every list holds an element once, so its next element is always after index
+            let mut count = 0;
+            let mut list = Some(list);
+            while let Some(current) = list {
+                count += 1;
+                if let Some(next) = iters[current].next() {
+                    heap.push(Reverse((next, current)));
                 }
+                list = match heap.peek() {
+                    Some(&Reverse((next, other))) if next == index => heap.pop().map(|_| other),
+                    _ => None,
+                };
+            }
+            if count >= min_shared {
+                by_count[count].push(index);
             }
         }
-        // Collect the indices that are marked as true
-        let mut result_indices = Vec::with_capacity(self.num_elements);
-        result_indices.extend(
-            indices
-                .iter()
-                .enumerate()
-                .filter_map(|(i, &contains)| if contains { Some(i) } else { None }),
-        );
-        result_indices.shrink_to_fit(); // Reduce capacity to the actual size
-        result_indices
+
+        let mut indices = Vec::new();
+        for group in by_count.iter().rev() {
+            if indices.len() >= limit {
+                break;
+            }
+            indices.extend_from_slice(&group[..group.len().min(limit - indices.len())]);
+        }
+        indices.sort_unstable();
+        indices
+    }
+    pub fn query_char(&self, c: char) -> Vec<ElementId> {
+        self.unigram_postings(c).map(|postings| postings.to_vec()).unwrap_or_default()
     }
 
     pub fn len(&self) -> usize {
-        // Return size of the index
-        self.index.len()
+        // Return size of the index, counting bigrams that only occur in the delta segment
+        self.index.len() + self.delta.keys().filter(|bigram| !self.index.contains_key(bigram)).count()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
     }
 }
 
-fn create_bigram_reverse_index(tree: &FileTree) -> HashMap<Bigram, CompressedPostingsList> {
-    println!("Creating bigram reverse index...");
-    let time_start = std::time::Instant::now();
-    // Create a bigram reverse index for the elements
-    let mut index: HashMap<Bigram, Vec<usize>> = HashMap::new();
-    for (i, element) in tree.get_elements().iter().enumerate() {
-        // take every two letters of the filename
-        let filename = tree.filename_as_str(&element.filename).to_lowercase();
-        // Split the query into bigrams (bi-letters)
-        let chars: Vec<char> = filename.chars().collect();
-        if chars.len() < 2 {
-            continue; // Skip elements with less than 2 characters
-        }
-        for j in 0..chars.len() - 1 {
-            // Create a bigram from the current and next character
-            let bigram = Bigram {
-                first: chars[j],
-                second: chars[j + 1],
-            };
-            index.entry(bigram).or_default().push(i);
+fn postings_with_delta<'a, K: Hash + Eq>(
+    index: &'a HashMap<K, Postings>,
+    delta: &DeltaSegment<K>,
+    key: &K,
+    num_elements: usize,
+) -> Option<Cow<'a, Postings>> {
+    match (index.get(key), delta.get(key)) {
+        (None, None) => None,
+        (Some(postings), None) => Some(Cow::Borrowed(postings)),
+        (postings, Some(delta)) => {
+            let postings = postings.map(Postings::to_vec).unwrap_or_default();
+            let merged = merged_postings(postings, delta);
+            Some(Cow::Owned(Postings::new(merged, num_elements)))
         }
     }
-    // Ensure indices are unique and sorted
-    for indices in index.values_mut() {
-        indices.sort_unstable(); // Should already be sorted, but just in case
-        indices.dedup(); // Remove duplicates
-        indices.shrink_to_fit(); // Reduce capacity to the actual number of indices
-    }
+}
+
+fn postings_memory_usage<K>(index: &HashMap<K, Postings>) -> usize {
+    index.values().map(Postings::memory_usage).sum()
+}
 
-    let mut compressed_index: HashMap<Bigram, CompressedPostingsList> = HashMap::new();
-    let mut total_size = 0;
-    for (bigram, indices) in index {
-        let comp_post = CompressedPostingsList::new(indices);
-        total_size += comp_post.indices.len(); // Calculate the size of the compressed postings list
-        compressed_index.insert(bigram, comp_post);
+fn merge_delta_into<K: Hash + Eq>(
+    index: &mut HashMap<K, Postings>,
+    delta: &mut DeltaSegment<K>,
+    num_elements: usize,
+) {
+    for (key, delta) in delta.drain() {
+        let postings = index.get(&key).map(Postings::to_vec).unwrap_or_default();
+        let merged = merged_postings(postings, &delta);
+        index.insert(key, Postings::new(merged, num_elements));
     }
+}
+
+// Number of threads used for building the index when none is given
+pub fn default_threads() -> usize {
+    std::thread::available_parallelism().map_or(1, |threads| threads.get())
+}
+
+type Shard = (HashMap<Bigram, Vec<ElementId>>, HashMap<char, Vec<ElementId>>);
+
+fn create_bigram_reverse_index(
+    tree: &FileTree,
+    threads: usize,
+) -> (HashMap<Bigram, Postings>, HashMap<char, Postings>) {
+    println!("Creating bigram reverse index with {} threads...", threads);
+    let time_start = std::time::Instant::now();
+    let num_elements = tree.len();
+    // More threads than elements would only create empty shards
+    let threads = threads.clamp(1, num_elements.max(1));
+    let shard_len = num_elements.div_ceil(threads).max(1);
+
+    // Index contiguous ranges of elements in parallel, so every shard has sorted postings
+    let shards: Vec<Shard> = std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..num_elements)
+            .step_by(shard_len)
+            .map(|start| scope.spawn(move || index_shard(tree, start..(start + shard_len).min(num_elements))))
+            .collect();
+        handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+    });
+    let (bigram_shards, unigram_shards): (Vec<_>, Vec<_>) = shards.into_iter().unzip();
+    let compressed_index = merge_shards(bigram_shards, threads, num_elements);
+    let unigrams = merge_shards(unigram_shards, threads, num_elements);
+
+    // Calculate the size of the postings lists
+    let total_size = postings_memory_usage(&compressed_index) + postings_memory_usage(&unigrams);
     println!(
-        "Created bigram reverse index with {} entries and total size of {} bytes in {:?}",
+        "Created bigram reverse index with {} entries, {} characters and total size of {} bytes in {:?}",
         compressed_index.len(),
+        unigrams.len(),
         total_size,
         time_start.elapsed()
     );
 
-    compressed_index
+    (compressed_index, unigrams)
+}
+
+// Merges the postings of the shards and compresses them
+fn merge_shards<K: Hash + Eq + Send>(
+    shards: Vec<HashMap<K, Vec<ElementId>>>,
+    threads: usize,
+    num_elements: usize,
+) -> HashMap<K, Postings> {
+    // Distribute the keys over the threads. The parts are kept in shard order, so
+    // concatenating them gives the sorted postings list of all elements.
+    let hasher = RandomState::new();
+    let mut partitions: Vec<HashMap<K, Vec<Vec<ElementId>>>> = (0..threads).map(|_| HashMap::new()).collect();
+    for shard in shards {
+        for (key, indices) in shard {
+            let partition = hasher.hash_one(&key) as usize % threads;
+            partitions[partition].entry(key).or_default().push(indices);
+        }
+    }
+
+    // Merge and compress each partition in parallel
+    let merged: Vec<Vec<(K, Postings)>> = std::thread::scope(|scope| {
+        let handles: Vec<_> = partitions
+            .into_iter()
+            .map(|partition| {
+                scope.spawn(move || {
+                    partition
+                        .into_iter()
+                        .map(|(key, parts)| (key, Postings::new(parts.concat(), num_elements)))
+                        .collect()
+                })
+            })
+            .collect();
+        handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+    });
+    merged.into_iter().flatten().collect()
+}
+
+// Bigram and character postings of the elements in the range, sorted and without duplicates
+fn index_shard(tree: &FileTree, range: std::ops::Range<usize>) -> Shard {
+    let mut index: HashMap<Bigram, Vec<ElementId>> = HashMap::new();
+    let mut unigrams: HashMap<char, Vec<ElementId>> = HashMap::new();
+    // Interned names share their Filename, so a name like index.js is only lowercased and split
+    // once per shard. Names are appended to the string buffer as elements are created, so only a
+    // name starting before the end of the names seen so far can be shared with an earlier element.
+    let mut shared_names: HashMap<Filename, Vec<char>> = HashMap::new();
+    let mut names_end = 0;
+    for i in range.map(ElementId::new) {
+        let filename = tree.filename(i);
+        let (start, end) = filename.bounds();
+        let lowercase_chars = || tree.get_filename(i).to_lowercase().chars().collect::<Vec<char>>();
+        let unshared;
+        let chars: &[char] = if start < names_end {
+            shared_names.entry(filename).or_insert_with(lowercase_chars)
+        } else {
+            unshared = lowercase_chars();
+            &unshared
+        };
+        names_end = names_end.max(end);
+        for pair in chars.windows(2) {
+            let bigram = Bigram {
+                first: pair[0],
+                second: pair[1],
+            };
+            push_posting(index.entry(bigram).or_default(), i);
+        }
+        for &c in chars {
+            push_posting(unigrams.entry(c).or_default(), i);
+        }
+    }
+    (index, unigrams)
+}
+
+fn push_posting(indices: &mut Vec<ElementId>, i: ElementId) {
+    // Elements are visited in order, so a repeated key can only be the last entry
+    if indices.last() != Some(&i) {
+        indices.push(i);
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::file_tree::RawId;
+
+    fn ids(indices: impl IntoIterator<Item = usize>) -> Vec<ElementId> {
+        indices.into_iter().map(ElementId::new).collect()
+    }
 
     #[test]
     fn test_compressed_postings_list() {
+        // The largest ids need the most bytes
+        let max = RawId::MAX as usize;
         let postings_list_tests = vec![
-            vec![1, 2, 3, 4, 5],
-            vec![100, 200, 300, 400],
-            vec![1, 42357, 845376, 845378, 1047637],
-            vec![
-                142357,
-                1844674407370955160,
-                1844674407370955161,
-                18446744073709551600,
-                18446744073709551615,
-            ],
+            ids([1, 2, 3, 4, 5]),
+            ids([100, 200, 300, 400]),
+            ids([1, 42357, 845376, 845378, 1047637]),
+            ids([142357, max / 10, max / 10 + 1, max - 15, max]),
         ];
         for postings_list in postings_list_tests {
             let compressed = CompressedPostingsList::new(postings_list.clone());
@@ -232,4 +642,126 @@ mod tests {
             assert_eq!(postings_list, decompressed);
         }
     }
+
+    #[test]
+    fn test_parallel_build() {
+        let mut tree = FileTree::with_capacity(100);
+        for i in 0..50 {
+            tree.add_or_update_recursive(&format!("dir{}\\file_{}.txt", i % 7, i * 37), None, None, None, 0);
+        }
+        tree.add_or_update_recursive("a", None, None, None, 0);
+
+        // Every thread count gives the same postings as a single thread
+        let single = BigramIndex::with_threads(&tree, 1);
+        for threads in [2, 3, 8, 1000] {
+            let parallel = BigramIndex::with_threads(&tree, threads);
+            assert_eq!(parallel.len(), single.len());
+            for (bigram, postings_list) in &single.index {
+                let parallel_list = &parallel.index[bigram];
+                assert_eq!(parallel_list.len(), postings_list.len());
+                assert_eq!(parallel_list.to_vec(), postings_list.to_vec(), "{:?}", bigram);
+            }
+            assert_eq!(parallel.unigrams.len(), single.unigrams.len());
+            for (c, postings_list) in &single.unigrams {
+                assert_eq!(parallel.unigrams[c].to_vec(), postings_list.to_vec(), "{:?}", c);
+            }
+        }
+        let expected: Vec<ElementId> = tree.ids().filter(|&i| tree.get_filename(i).contains("e_3")).collect();
+        assert_eq!(single.query_word("e_3"), expected);
+    }
+
+    #[test]
+    fn test_interned_names() {
+        let mut tree = FileTree::with_capacity(10);
+        tree.start_interning();
+        let first = tree.add_or_update_recursive("a\\Index.js", None, None, None, 0);
+        let second = tree.add_or_update_recursive("b\\Index.js", None, None, None, 0);
+        tree.stop_interning();
+        let third = tree.add_or_update_recursive("c\\Index.js", None, None, None, 0);
+
+        // Elements sharing a name still get their own postings
+        let index = BigramIndex::with_threads(&tree, 1);
+        assert_eq!(index.query_word("x.j"), vec![first, second, third]);
+        assert_eq!(index.query_char('i'), vec![first, second, third]);
+        assert_eq!(BigramIndex::with_threads(&tree, 3).query_word("x.j"), vec![first, second, third]);
+    }
+
+    #[test]
+    fn test_query_char() {
+        let mut tree = FileTree::with_capacity(10);
+        tree.add_or_update_recursive("a\\ab\\B", None, None, None, 0);
+        tree.add_or_update_recursive("c", None, None, None, 0);
+        let mut index = BigramIndex::with_threads(&tree, 1);
+        // Single character names have no bigrams but are found by their character
+        assert_eq!(index.query_char('a'), ids([1, 2]));
+        assert_eq!(index.query_char('b'), ids([2, 3]));
+        assert_eq!(index.query_char('c'), ids([4]));
+        assert_eq!(index.query_char('x'), Vec::new());
+        // Words too short for bigrams don't panic
+        assert_eq!(index.query_word("c"), ids([4]));
+        assert_eq!(index.query_word("x"), Vec::new());
+        assert_eq!(index.query_word(""), Vec::new());
+
+        tree.add_or_update_recursive("x", None, None, None, 0);
+        tree.add_or_update_recursive("a\\box", None, None, None, 0);
+        index.add_elements(&tree);
+        assert!(index.has_delta());
+        assert_eq!(index.query_char('x'), ids([5, 6]));
+        index.merge_delta();
+        assert!(!index.has_delta());
+        assert_eq!(index.query_char('x'), ids([5, 6]));
+        assert_eq!(index.query_char('b'), ids([2, 3, 6]));
+    }
+
+    #[test]
+    fn test_query_overlap() {
+        let mut tree = FileTree::with_capacity(10);
+        for name in ["invoice", "invoke", "voice", "vote", "zzz"] {
+            tree.add_or_update_recursive(name, None, None, None, 0);
+        }
+        let index = BigramIndex::with_threads(&tree, 1);
+        // in, nv, vo, oi, ic, ce
+        assert_eq!(index.query_overlap("invoice", 6, 10), ids([1]));
+        assert_eq!(index.query_overlap("invoice", 3, 10), ids([1, 2, 3]));
+        assert_eq!(index.query_overlap("invoice", 1, 10), ids([1, 2, 3, 4]));
+        // The limit keeps the elements sharing the most bigrams
+        assert_eq!(index.query_overlap("invoice", 1, 2), ids([1, 3]));
+        assert_eq!(index.query_overlap("invoice", 1, 0), Vec::new());
+        assert_eq!(index.query_overlap("qq", 1, 10), Vec::new());
+    }
+
+    #[test]
+    fn test_skip_pointers() {
+        let postings = ids((0..1000).map(|i| i * 10 + i * i % 7));
+        let compressed = CompressedPostingsList::new(postings.clone());
+        assert_eq!(compressed.skips.len(), postings.len().div_ceil(SKIP_INTERVAL));
+        let restored = CompressedPostingsList::from_encoded(compressed.indices.clone(), compressed.length);
+        assert_eq!(restored.skips, compressed.skips);
+        for block in 0..compressed.skips.len() {
+            let start = block * SKIP_INTERVAL;
+            let end = (start + SKIP_INTERVAL).min(postings.len());
+            assert_eq!(compressed.decode_block(block), postings[start..end]);
+        }
+
+        // Few candidates use the skip pointers, many are merged, both give the same result
+        for step in [1, 3, 97, 4001] {
+            let candidates = ids((0..30_000).step_by(step));
+            let expected: Vec<ElementId> = candidates.iter().copied().filter(|c| postings.contains(c)).collect();
+            assert_eq!(compressed.intersect_sorted(&candidates), expected, "step {}", step);
+            assert_eq!(intersect_sorted(&candidates, &postings), expected, "step {}", step);
+        }
+        assert_eq!(compressed.intersect_sorted(&[]), Vec::new());
+        assert_eq!(CompressedPostingsList::new(Vec::new()).intersect_sorted(&ids([1, 2])), Vec::new());
+    }
+
+    #[test]
+    fn test_gallop() {
+        let values = [1, 3, 5, 7, 9, 11, 13];
+        for start in 0..=values.len() {
+            for x in 0..15 {
+                let expected = start + values[start..].partition_point(|&v| v < x);
+                assert_eq!(gallop(&values, start, |&v| v < x), expected, "start {} x {}", start, x);
+            }
+        }
+    }
 }
@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use crate::file_tree::ElementId;
+
+// Number of postings collected in a delta segment before it is merged into the compressed lists
+pub const MERGE_THRESHOLD: usize = 1 << 16;
+
+// Postings of elements added or renamed after an index was built, kept uncompressed until there
+// are enough of them to merge. New elements get higher indices than the indexed ones, so merging
+// mostly appends to the compressed lists.
+pub struct DeltaSegment<K> {
+    postings: HashMap<K, Vec<ElementId>>,
+    num_postings: usize,
+}
+impl<K: Hash + Eq> Default for DeltaSegment<K> {
+    fn default() -> Self {
+        DeltaSegment {
+            postings: HashMap::new(),
+            num_postings: 0,
+        }
+    }
+}
+impl<K: Hash + Eq> DeltaSegment<K> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Keeps the postings sorted, elements added in index order are simply appended
+    pub fn add(&mut self, key: K, index: ElementId) {
+        let postings = self.postings.entry(key).or_default();
+        match postings.last() {
+            Some(&last) if last >= index => {
+                if let Err(position) = postings.binary_search(&index) {
+                    postings.insert(position, index);
+                    self.num_postings += 1;
+                }
+            }
+            _ => {
+                postings.push(index);
+                self.num_postings += 1;
+            }
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Option<&[ElementId]> {
+        self.postings.get(key).map(Vec::as_slice)
+    }
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.postings.keys()
+    }
+    pub fn num_postings(&self) -> usize {
+        self.num_postings
+    }
+    pub fn is_empty(&self) -> bool {
+        self.num_postings == 0
+    }
+    pub fn should_merge(&self) -> bool {
+        self.num_postings >= MERGE_THRESHOLD
+    }
+
+    // Removes all pending postings, to be merged into the index with merged_postings
+    pub fn drain(&mut self) -> impl Iterator<Item = (K, Vec<ElementId>)> + '_ {
+        self.num_postings = 0;
+        self.postings.drain()
+    }
+
+    // Approximate heap size in bytes
+    pub fn memory_usage(&self) -> usize {
+        self.postings
+            .values()
+            .map(|postings| postings.capacity() * std::mem::size_of::<ElementId>())
+            .sum::<usize>()
+            + self.postings.capacity() * std::mem::size_of::<(K, Vec<ElementId>)>()
+    }
+}
+
+// The sorted union of the postings of the index and the delta segment
+pub fn merged_postings(mut postings: Vec<ElementId>, delta: &[ElementId]) -> Vec<ElementId> {
+    match (postings.last(), delta.first()) {
+        // Renamed elements can be in both lists and in between the indexed postings
+        (Some(&last), Some(&first)) if first <= last => {
+            let mut merged = Vec::with_capacity(postings.len() + delta.len());
+            let (mut i, mut j) = (0, 0);
+            while i < postings.len() && j < delta.len() {
+                if postings[i] < delta[j] {
+                    merged.push(postings[i]);
+                    i += 1;
+                } else {
+                    if postings[i] == delta[j] {
+                        i += 1;
+                    }
+                    merged.push(delta[j]);
+                    j += 1;
+                }
+            }
+            merged.extend_from_slice(&postings[i..]);
+            merged.extend_from_slice(&delta[j..]);
+            merged
+        }
+        _ => {
+            postings.extend_from_slice(delta);
+            postings
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids<const N: usize>(indices: [usize; N]) -> Vec<ElementId> {
+        indices.map(ElementId::new).to_vec()
+    }
+
+    #[test]
+    fn test_delta_segment() {
+        let indexed = ids([1, 4]);
+        let mut delta = DeltaSegment::new();
+        delta.add("a", ElementId::new(7));
+        delta.add("a", ElementId::new(7));
+        delta.add("b", ElementId::new(7));
+        delta.add("a", ElementId::new(9));
+        assert_eq!(delta.num_postings(), 3);
+        assert_eq!(merged_postings(indexed.clone(), delta.get(&"a").unwrap()), ids([1, 4, 7, 9]));
+        assert_eq!(merged_postings(Vec::new(), delta.get(&"b").unwrap()), ids([7]));
+        // Renamed elements are inserted in order
+        delta.add("a", ElementId::new(4));
+        delta.add("a", ElementId::new(2));
+        assert_eq!(delta.get(&"a"), Some(&ids([2, 4, 7, 9])[..]));
+        assert_eq!(merged_postings(indexed, delta.get(&"a").unwrap()), ids([1, 2, 4, 7, 9]));
+
+        let mut drained: Vec<(&str, Vec<ElementId>)> = delta.drain().collect();
+        drained.sort();
+        assert_eq!(drained, vec![("a", ids([2, 4, 7, 9])), ("b", ids([7]))]);
+        assert!(delta.is_empty());
+    }
+}
@@ -1 +1,5 @@
 pub mod bigram_index;
+pub mod delta_segment;
+pub mod postings;
+pub mod prefix_index;
+pub mod text_index;
@@ -0,0 +1,491 @@
+use std::borrow::Cow;
+
+use crate::file_tree::ElementId;
+use crate::indexer::bigram_index::CompressedPostingsList;
+
+// Lists containing at least one in this many elements are stored as bitmaps
+pub const BITMAP_MIN_DENSITY: usize = 16;
+
+// Elements are grouped into chunks by their upper bits, like in roaring bitmaps
+const CHUNK_BITS: u32 = 16;
+const CHUNK_SIZE: usize = 1 << CHUNK_BITS;
+pub(crate) const BITMAP_WORDS: usize = CHUNK_SIZE / 64;
+// Chunks with more elements than this are stored as a bitmap, fewer as a sorted array
+const ARRAY_MAX_LEN: usize = 4096;
+
+// A postings list, either as variable byte gaps for sparse lists or as a bitmap for dense ones
+#[derive(Clone)]
+pub enum Postings {
+    VByte(CompressedPostingsList),
+    Bitmap(BitmapPostings),
+}
+impl Postings {
+    // Picks the representation based on how many of all elements are in the list
+    pub fn new(indices: Vec<ElementId>, num_elements: usize) -> Self {
+        if !indices.is_empty() && indices.len() * BITMAP_MIN_DENSITY >= num_elements {
+            Postings::Bitmap(BitmapPostings::from_sorted(&indices))
+        } else {
+            Postings::VByte(CompressedPostingsList::new(indices))
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Postings::VByte(list) => list.length,
+            Postings::Bitmap(bitmap) => bitmap.len(),
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn to_vec(&self) -> Vec<ElementId> {
+        match self {
+            Postings::VByte(list) => list.decompress(),
+            Postings::Bitmap(bitmap) => bitmap.to_vec(),
+        }
+    }
+    // Iterates over the sorted elements, decoding one block or chunk at a time
+    pub fn iter(&self) -> Box<dyn Iterator<Item = ElementId> + '_> {
+        match self {
+            Postings::VByte(list) => Box::new(list.iter()),
+            Postings::Bitmap(bitmap) => Box::new(bitmap.iter()),
+        }
+    }
+
+    // Bitmaps are borrowed, variable byte lists are converted block by block
+    pub fn to_bitmap(&self) -> Cow<'_, BitmapPostings> {
+        match self {
+            Postings::VByte(list) => Cow::Owned(BitmapPostings::from_sorted_iter(list.iter())),
+            Postings::Bitmap(bitmap) => Cow::Borrowed(bitmap),
+        }
+    }
+
+    // The elements both in this list and in the bitmap
+    pub fn intersect(&self, other: &BitmapPostings) -> BitmapPostings {
+        match self {
+            Postings::VByte(list) => {
+                BitmapPostings::from_sorted_iter(list.iter().filter(|&index| other.contains(index)))
+            }
+            Postings::Bitmap(bitmap) => bitmap.and(other),
+        }
+    }
+
+    // The elements in either list. Bitmaps are combined chunk by chunk, variable byte lists are
+    // merged while decoding them block by block.
+    pub fn union(&self, other: &Postings) -> Postings {
+        match (self, other) {
+            (Postings::Bitmap(a), Postings::Bitmap(b)) => Postings::Bitmap(a.or(b)),
+            (Postings::Bitmap(bitmap), Postings::VByte(list))
+            | (Postings::VByte(list), Postings::Bitmap(bitmap)) => {
+                Postings::Bitmap(bitmap.or(&BitmapPostings::from_sorted_iter(list.iter())))
+            }
+            (Postings::VByte(a), Postings::VByte(b)) => {
+                let mut indices = Vec::with_capacity(a.length + b.length);
+                let (mut a, mut b) = (a.iter().peekable(), b.iter().peekable());
+                while let (Some(&x), Some(&y)) = (a.peek(), b.peek()) {
+                    let next = x.min(y);
+                    indices.push(next);
+                    a.next_if_eq(&next);
+                    b.next_if_eq(&next);
+                }
+                indices.extend(a.chain(b));
+                Postings::VByte(CompressedPostingsList::new(indices))
+            }
+        }
+    }
+
+    // The elements of this list that are not in the other. Only the blocks of a variable byte
+    // list that this list's elements fall into are decoded.
+    pub fn difference(&self, other: &Postings) -> Postings {
+        match (self, other) {
+            (Postings::Bitmap(a), Postings::Bitmap(b)) => Postings::Bitmap(a.and_not(b)),
+            (Postings::Bitmap(a), Postings::VByte(b)) => {
+                Postings::Bitmap(a.and_not(&BitmapPostings::from_sorted_iter(b.iter())))
+            }
+            (Postings::VByte(a), Postings::Bitmap(b)) => {
+                let indices = a.iter().filter(|&index| !b.contains(index)).collect();
+                Postings::VByte(CompressedPostingsList::new(indices))
+            }
+            (Postings::VByte(a), Postings::VByte(b)) => {
+                let indices: Vec<ElementId> = a.iter().collect();
+                Postings::VByte(CompressedPostingsList::new(b.difference_sorted(&indices)))
+            }
+        }
+    }
+
+    // The sorted candidates that are in this list, without decompressing the whole list
+    pub fn intersect_sorted(&self, candidates: &[ElementId]) -> Vec<ElementId> {
+        match self {
+            Postings::VByte(list) => list.intersect_sorted(candidates),
+            Postings::Bitmap(bitmap) => candidates.iter().copied().filter(|&index| bitmap.contains(index)).collect(),
+        }
+    }
+
+    // Approximate heap size in bytes
+    pub fn memory_usage(&self) -> usize {
+        match self {
+            Postings::VByte(list) => list.memory_usage(),
+            Postings::Bitmap(bitmap) => bitmap.memory_usage(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Container {
+    Array(Vec<u16>),
+    Bitmap(Box<[u64]>),
+}
+impl Container {
+    fn from_sorted(lows: Vec<u16>) -> Self {
+        Container::Array(lows).normalized()
+    }
+
+    fn empty_bitmap() -> Box<[u64]> {
+        vec![0; BITMAP_WORDS].into_boxed_slice()
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            Container::Array(lows) => lows.len(),
+            Container::Bitmap(words) => words.iter().map(|word| word.count_ones() as usize).sum(),
+        }
+    }
+
+    fn contains(&self, low: u16) -> bool {
+        match self {
+            Container::Array(lows) => lows.binary_search(&low).is_ok(),
+            Container::Bitmap(words) => words[low as usize / 64] & (1 << (low % 64)) != 0,
+        }
+    }
+
+    fn lows(&self) -> Vec<u16> {
+        match self {
+            Container::Array(lows) => lows.clone(),
+            Container::Bitmap(words) => {
+                let mut lows = Vec::with_capacity(self.len());
+                for (i, &word) in words.iter().enumerate() {
+                    let mut word = word;
+                    while word != 0 {
+                        lows.push((i * 64 + word.trailing_zeros() as usize) as u16);
+                        word &= word - 1;
+                    }
+                }
+                lows
+            }
+        }
+    }
+
+    // Uses the smaller representation for the number of elements
+    fn normalized(self) -> Self {
+        let len = self.len();
+        match self {
+            Container::Array(lows) if lows.len() > ARRAY_MAX_LEN => {
+                let mut words = Container::empty_bitmap();
+                for low in lows {
+                    words[low as usize / 64] |= 1 << (low % 64);
+                }
+                Container::Bitmap(words)
+            }
+            Container::Bitmap(_) if len <= ARRAY_MAX_LEN => Container::Array(self.lows()),
+            container => container,
+        }
+    }
+
+    fn and(&self, other: &Container) -> Container {
+        match (self, other) {
+            (Container::Bitmap(a), Container::Bitmap(b)) => {
+                Container::Bitmap(a.iter().zip(b.iter()).map(|(a, b)| a & b).collect()).normalized()
+            }
+            (Container::Array(lows), other) | (other, Container::Array(lows)) => {
+                Container::Array(lows.iter().copied().filter(|&low| other.contains(low)).collect())
+            }
+        }
+    }
+
+    fn or(&self, other: &Container) -> Container {
+        match (self, other) {
+            (Container::Array(a), Container::Array(b)) => {
+                let mut lows = Vec::with_capacity(a.len() + b.len());
+                let (mut i, mut j) = (0, 0);
+                while i < a.len() && j < b.len() {
+                    match a[i].cmp(&b[j]) {
+                        std::cmp::Ordering::Less => {
+                            lows.push(a[i]);
+                            i += 1;
+                        }
+                        std::cmp::Ordering::Greater => {
+                            lows.push(b[j]);
+                            j += 1;
+                        }
+                        std::cmp::Ordering::Equal => {
+                            lows.push(a[i]);
+                            i += 1;
+                            j += 1;
+                        }
+                    }
+                }
+                lows.extend_from_slice(&a[i..]);
+                lows.extend_from_slice(&b[j..]);
+                Container::from_sorted(lows)
+            }
+            (Container::Bitmap(a), Container::Bitmap(b)) => {
+                Container::Bitmap(a.iter().zip(b.iter()).map(|(a, b)| a | b).collect())
+            }
+            (Container::Bitmap(words), Container::Array(lows))
+            | (Container::Array(lows), Container::Bitmap(words)) => {
+                let mut words = words.clone();
+                for &low in lows {
+                    words[low as usize / 64] |= 1 << (low % 64);
+                }
+                Container::Bitmap(words)
+            }
+        }
+    }
+
+    fn and_not(&self, other: &Container) -> Container {
+        match (self, other) {
+            (Container::Array(lows), other) => {
+                Container::Array(lows.iter().copied().filter(|&low| !other.contains(low)).collect())
+            }
+            (Container::Bitmap(a), Container::Bitmap(b)) => {
+                Container::Bitmap(a.iter().zip(b.iter()).map(|(a, b)| a & !b).collect()).normalized()
+            }
+            (Container::Bitmap(words), Container::Array(lows)) => {
+                let mut words = words.clone();
+                for &low in lows {
+                    words[low as usize / 64] &= !(1 << (low % 64));
+                }
+                Container::Bitmap(words).normalized()
+            }
+        }
+    }
+
+    fn memory_usage(&self) -> usize {
+        match self {
+            Container::Array(lows) => lows.capacity() * std::mem::size_of::<u16>(),
+            Container::Bitmap(words) => words.len() * std::mem::size_of::<u64>(),
+        }
+    }
+}
+
+// Roaring-style set of element indices: the indices are split into chunks by their upper bits,
+// each chunk stores its lower 16 bits as a sorted array or as a bitmap. Set operations work
+// chunk by chunk without expanding the sets into lists.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BitmapPostings {
+    pub(crate) keys: Vec<usize>, // Upper bits of the chunks, sorted
+    pub(crate) containers: Vec<Container>,
+}
+impl BitmapPostings {
+    pub fn from_sorted(indices: &[ElementId]) -> Self {
+        Self::from_sorted_iter(indices.iter().copied())
+    }
+    // Builds the chunks while reading the sorted indices, without collecting them first
+    pub fn from_sorted_iter(indices: impl IntoIterator<Item = ElementId>) -> Self {
+        let mut bitmap = BitmapPostings::default();
+        let mut key = 0;
+        let mut lows = Vec::new();
+        for index in indices {
+            if index.index() >> CHUNK_BITS != key {
+                bitmap.push(key, Container::from_sorted(std::mem::take(&mut lows)));
+                key = index.index() >> CHUNK_BITS;
+            }
+            lows.push((index.index() % CHUNK_SIZE) as u16);
+        }
+        bitmap.push(key, Container::from_sorted(lows));
+        bitmap
+    }
+
+    // Rebuilds a bitmap from its chunks, which have to be sorted by key
+    pub(crate) fn from_containers(keys: Vec<usize>, containers: Vec<Container>) -> Self {
+        BitmapPostings { keys, containers }
+    }
+
+    fn push(&mut self, key: usize, container: Container) {
+        if container.len() > 0 {
+            self.keys.push(key);
+            self.containers.push(container);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.containers.iter().map(Container::len).sum()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.containers.is_empty()
+    }
+
+    pub fn contains(&self, index: ElementId) -> bool {
+        let index = index.index();
+        match self.keys.binary_search(&(index >> CHUNK_BITS)) {
+            Ok(i) => self.containers[i].contains((index % CHUNK_SIZE) as u16),
+            Err(_) => false,
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = ElementId> + '_ {
+        self.keys.iter().zip(&self.containers).flat_map(|(&key, container)| {
+            container.lows().into_iter().map(move |low| ElementId::new((key << CHUNK_BITS) | low as usize))
+        })
+    }
+
+    pub fn to_vec(&self) -> Vec<ElementId> {
+        let mut indices = Vec::with_capacity(self.len());
+        for (&key, container) in self.keys.iter().zip(&self.containers) {
+            let ids = container.lows().into_iter();
+            indices.extend(ids.map(|low| ElementId::new((key << CHUNK_BITS) | low as usize)));
+        }
+        indices
+    }
+
+    pub fn and(&self, other: &BitmapPostings) -> BitmapPostings {
+        let mut result = BitmapPostings::default();
+        let (mut i, mut j) = (0, 0);
+        while i < self.keys.len() && j < other.keys.len() {
+            match self.keys[i].cmp(&other.keys[j]) {
+                std::cmp::Ordering::Less => i += 1,
+                std::cmp::Ordering::Greater => j += 1,
+                std::cmp::Ordering::Equal => {
+                    result.push(self.keys[i], self.containers[i].and(&other.containers[j]));
+                    i += 1;
+                    j += 1;
+                }
+            }
+        }
+        result
+    }
+
+    pub fn or(&self, other: &BitmapPostings) -> BitmapPostings {
+        let mut result = BitmapPostings::default();
+        let (mut i, mut j) = (0, 0);
+        while i < self.keys.len() || j < other.keys.len() {
+            let order = match (self.keys.get(i), other.keys.get(j)) {
+                (Some(a), Some(b)) => a.cmp(b),
+                (Some(_), None) => std::cmp::Ordering::Less,
+                _ => std::cmp::Ordering::Greater,
+            };
+            match order {
+                std::cmp::Ordering::Less => {
+                    result.push(self.keys[i], self.containers[i].clone());
+                    i += 1;
+                }
+                std::cmp::Ordering::Greater => {
+                    result.push(other.keys[j], other.containers[j].clone());
+                    j += 1;
+                }
+                std::cmp::Ordering::Equal => {
+                    result.push(self.keys[i], self.containers[i].or(&other.containers[j]));
+                    i += 1;
+                    j += 1;
+                }
+            }
+        }
+        result
+    }
+
+    // The elements of this set that are not in the other
+    pub fn and_not(&self, other: &BitmapPostings) -> BitmapPostings {
+        let mut result = BitmapPostings::default();
+        let mut j = 0;
+        for (i, &key) in self.keys.iter().enumerate() {
+            while j < other.keys.len() && other.keys[j] < key {
+                j += 1;
+            }
+            let container = match other.keys.get(j) {
+                Some(&other_key) if other_key == key => self.containers[i].and_not(&other.containers[j]),
+                _ => self.containers[i].clone(),
+            };
+            result.push(key, container);
+        }
+        result
+    }
+
+    // Approximate heap size in bytes
+    pub fn memory_usage(&self) -> usize {
+        self.keys.capacity() * std::mem::size_of::<usize>()
+            + self.containers.capacity() * std::mem::size_of::<Container>()
+            + self.containers.iter().map(Container::memory_usage).sum::<usize>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_tree::RawId;
+
+    fn ids(indices: impl IntoIterator<Item = usize>) -> Vec<ElementId> {
+        indices.into_iter().map(ElementId::new).collect()
+    }
+
+    #[test]
+    fn test_bitmap_operations() {
+        // Sparse and dense chunks, spanning several chunk keys up to the largest id
+        let max = RawId::MAX as usize;
+        let a = ids((0..200_000).filter(|i| i % 3 == 0 || (i % 1000 == 7)));
+        let b = ids((0..300_000).filter(|i| i % 5 == 0 && *i > 60_000).chain([max]));
+        let c = ids([3, 70_000, 140_001, max]);
+        let bitmaps: Vec<BitmapPostings> = [&a, &b, &c].map(|list| BitmapPostings::from_sorted(list)).into();
+        let lists = [&a, &b, &c];
+        for (x, bitmap) in lists.iter().zip(&bitmaps) {
+            assert_eq!(&&bitmap.to_vec(), x);
+            assert_eq!(bitmap.len(), x.len());
+        }
+
+        for (i, x) in lists.iter().enumerate() {
+            for (j, y) in lists.iter().enumerate() {
+                let (bx, by) = (&bitmaps[i], &bitmaps[j]);
+                let and: Vec<ElementId> = x.iter().copied().filter(|v| y.binary_search(v).is_ok()).collect();
+                let and_not: Vec<ElementId> = x.iter().copied().filter(|v| y.binary_search(v).is_err()).collect();
+                let mut or: Vec<ElementId> = x.iter().chain(y.iter()).copied().collect();
+                or.sort_unstable();
+                or.dedup();
+                assert_eq!(bx.and(by).to_vec(), and, "{} and {}", i, j);
+                assert_eq!(bx.and_not(by).to_vec(), and_not, "{} and not {}", i, j);
+                assert_eq!(bx.or(by).to_vec(), or, "{} or {}", i, j);
+            }
+        }
+        assert!(bitmaps[0].contains(ElementId::new(3)) && !bitmaps[0].contains(ElementId::new(4)));
+        assert!(bitmaps[1].contains(ElementId::new(max)));
+        assert!(bitmaps[2].and(&BitmapPostings::default()).is_empty());
+    }
+
+    #[test]
+    fn test_postings_density() {
+        let dense = ids((0..1000).step_by(2));
+        let sparse = ids([5, 500, 999]);
+        let dense_postings = Postings::new(dense.clone(), 1000);
+        let sparse_postings = Postings::new(sparse.clone(), 1000);
+        assert!(matches!(dense_postings, Postings::Bitmap(_)));
+        assert!(matches!(sparse_postings, Postings::VByte(_)));
+        assert_eq!(dense_postings.to_vec(), dense);
+        assert_eq!(sparse_postings.len(), 3);
+
+        // Both kinds intersect with a bitmap to the same result
+        let odd = BitmapPostings::from_sorted(&ids((1..1000).step_by(2)));
+        assert_eq!(sparse_postings.intersect(&odd).to_vec(), ids([5, 999]));
+        assert!(dense_postings.intersect(&odd).is_empty());
+        assert_eq!(sparse_postings.intersect(&dense_postings.to_bitmap()).to_vec(), ids([500]));
+    }
+
+    #[test]
+    fn test_postings_union_difference() {
+        // Every pair of representations gives the same results as on plain lists
+        let lists = [ids((0..5000).step_by(3)), ids((0..5000).step_by(2)), ids([1, 2, 600, 4998, 4999])];
+        for x in &lists {
+            for y in &lists {
+                let mut or: Vec<ElementId> = x.iter().chain(y).copied().collect();
+                or.sort_unstable();
+                or.dedup();
+                let and_not: Vec<ElementId> = x.iter().copied().filter(|v| y.binary_search(v).is_err()).collect();
+                for num_elements in [x.len() * 2, x.len() * 100] {
+                    let a = Postings::new(x.clone(), num_elements);
+                    for b in [Postings::new(y.clone(), y.len() * 2), Postings::new(y.clone(), y.len() * 100)] {
+                        assert_eq!(a.union(&b).to_vec(), or);
+                        assert_eq!(a.difference(&b).to_vec(), and_not);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use std::cmp::Ordering;
+use std::ops::Range;
+
+use crate::file_tree::{ElementId, FileTree};
+use crate::memory::vec_bytes;
+use crate::sorter::{Sorter, cmp_filenames};
+
+// Element indices sorted by filename ignoring case, so all names with a given prefix
+// form a contiguous range that is found by binary search
+pub struct PrefixIndex {
+    sorted: Vec<ElementId>,
+}
+impl PrefixIndex {
+    pub fn new(tree: &FileTree, sorter: &Sorter) -> Self {
+        println!("Creating prefix index...");
+        let time_start = std::time::Instant::now();
+        // Reuse the filename order of the sorter, without the root and removed elements
+        let mut sorted = sorter.filename_permutation(tree);
+        sorted.retain(|&index| index != ElementId::ROOT && !tree.is_removed(index));
+        println!(
+            "Created prefix index with {} entries in {:?}",
+            sorted.len(),
+            time_start.elapsed()
+        );
+        PrefixIndex { sorted }
+    }
+
+    // Positions in the sorted list of all filenames starting with the prefix, ignoring case
+    fn range(&self, tree: &FileTree, prefix: &str) -> Range<usize> {
+        let start = self
+            .sorted
+            .partition_point(|&index| cmp_prefix(tree.get_filename(index), prefix) == Ordering::Less);
+        let end = start
+            + self.sorted[start..]
+                .partition_point(|&index| cmp_prefix(tree.get_filename(index), prefix) == Ordering::Equal);
+        start..end
+    }
+
+    // Indices of all elements whose filename starts with the prefix, in index order
+    pub fn query_prefix(&self, tree: &FileTree, prefix: &str) -> Vec<ElementId> {
+        let mut indices = self.sorted[self.range(tree, prefix)].to_vec();
+        indices.sort_unstable();
+        indices
+    }
+
+    // The first n distinct filenames starting with the prefix, in alphabetical order
+    pub fn top_filenames<'a>(&self, tree: &'a FileTree, prefix: &str, n: usize) -> Vec<&'a str> {
+        let mut filenames: Vec<&str> = Vec::with_capacity(n);
+        let mut matches = &self.sorted[self.range(tree, prefix)];
+        while let Some(&index) = matches.first()
+            && filenames.len() < n
+        {
+            let filename = tree.get_filename(index);
+            filenames.push(filename);
+            // Equal names are next to each other in the sorted list, skip them by binary search
+            let equal = |&other: &ElementId| cmp_filenames(tree.get_filename(other), filename).is_eq();
+            matches = &matches[matches.partition_point(equal)..];
+        }
+        filenames
+    }
+
+    pub fn len(&self) -> usize {
+        self.sorted.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.sorted.is_empty()
+    }
+    pub fn memory_usage(&self) -> usize {
+        vec_bytes(&self.sorted)
+    }
+}
+
+// Compares the start of the filename with the prefix ignoring case, Equal if the filename starts with it
+fn cmp_prefix(filename: &str, prefix: &str) -> Ordering {
+    let mut filename_chars = filename.chars().flat_map(char::to_lowercase);
+    for prefix_char in prefix.chars().flat_map(char::to_lowercase) {
+        match filename_chars.next() {
+            Some(filename_char) if filename_char == prefix_char => continue,
+            Some(filename_char) => return filename_char.cmp(&prefix_char),
+            None => return Ordering::Less,
+        }
+    }
+    Ordering::Equal
+}
+
+// Whether the filename starts with the prefix, ignoring case
+pub fn starts_with_ignore_case(filename: &str, prefix: &str) -> bool {
+    cmp_prefix(filename, prefix) == Ordering::Equal
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prefix_index() {
+        let mut tree = FileTree::with_capacity(10);
+        let readme = tree.add_or_update_recursive("project/README.md", None, None, None, 0);
+        let readme_copy = tree.add_or_update_recursive("backup/readme.md", None, None, None, 0);
+        let reader = tree.add_or_update_recursive("project/src/reader.rs", None, None, None, 0);
+        tree.add_or_update_recursive("project/src/main.rs", None, None, None, 0);
+        tree.add_or_update_recursive("project/re", None, None, None, 0);
+
+        let index = PrefixIndex::new(&tree, &Sorter::new());
+        assert_eq!(index.len(), tree.len() - 1);
+        assert_eq!(index.query_prefix(&tree, "read"), vec![readme, readme_copy, reader]);
+        assert_eq!(index.query_prefix(&tree, "READM"), vec![readme, readme_copy]);
+        assert_eq!(index.query_prefix(&tree, "readme.md.bak"), Vec::new());
+        assert_eq!(index.query_prefix(&tree, "zzz"), Vec::new());
+        assert_eq!(index.query_prefix(&tree, "").len(), index.len());
+
+        assert_eq!(index.top_filenames(&tree, "re", 10), vec!["re", "reader.rs", "README.md", "readme.md"]);
+        assert_eq!(index.top_filenames(&tree, "re", 2), vec!["re", "reader.rs"]);
+        assert_eq!(index.top_filenames(&tree, "p", 10), vec!["project"]);
+
+        // Many equal names count once
+        for i in 0..100 {
+            tree.add_or_update_recursive(&format!("copy{}/index.js", i), None, None, None, 0);
+        }
+        tree.add_or_update_recursive("src/index.json", None, None, None, 0);
+        let index = PrefixIndex::new(&tree, &Sorter::new());
+        assert_eq!(index.top_filenames(&tree, "ind", 10), vec!["index.js", "index.json"]);
+        assert_eq!(index.top_filenames(&tree, "ind", 1), vec!["index.js"]);
+    }
+}
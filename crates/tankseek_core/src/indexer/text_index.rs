@@ -0,0 +1,226 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use crate::file_tree::{ElementId, FileTree};
+use crate::indexer::bigram_index::{BigramIndex, CompressedPostingsList};
+use crate::indexer::delta_segment::{DeltaSegment, merged_postings};
+
+// An index over the lowercased filenames that narrows down which elements may contain a text
+pub trait TextIndex: Send + Sync {
+    // Length of the grams, texts shorter than this can't be looked up
+    fn gram_size(&self) -> usize;
+
+    // Sorted indices of all elements whose filename may contain the lowercased text.
+    // Every real match is included, candidates still have to be checked.
+    fn candidates(&self, text: &str) -> Vec<ElementId>;
+
+    // Number of distinct grams in the index
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Approximate heap size in bytes
+    fn memory_usage(&self) -> usize;
+}
+
+impl TextIndex for BigramIndex {
+    fn gram_size(&self) -> usize {
+        2
+    }
+
+    fn candidates(&self, text: &str) -> Vec<ElementId> {
+        let mut chars = text.chars();
+        match (chars.next(), chars.next()) {
+            (None, _) => Vec::new(),
+            (Some(c), None) => self.query_char(c),
+            _ => self.query_word(text),
+        }
+    }
+
+    fn len(&self) -> usize {
+        BigramIndex::len(self)
+    }
+
+    fn memory_usage(&self) -> usize {
+        BigramIndex::memory_usage(self).bytes
+    }
+}
+
+// Index of all substrings of n characters, n >= 2. Longer grams are more selective,
+// so fewer candidates have to be checked, at the cost of more memory.
+pub struct NgramIndex {
+    n: usize,
+    index: HashMap<String, CompressedPostingsList>,
+    // Elements added since the index was built, see add_elements
+    delta: DeltaSegment<String>,
+    num_elements: usize,
+}
+impl NgramIndex {
+    pub fn new(tree: &FileTree, n: usize) -> Self {
+        assert!(n >= 2, "n-grams need at least 2 characters");
+        println!("Creating {}-gram reverse index...", n);
+        let time_start = std::time::Instant::now();
+        let mut index: HashMap<String, Vec<ElementId>> = HashMap::new();
+        for i in tree.ids() {
+            let filename = tree.get_filename(i).to_lowercase();
+            let chars: Vec<char> = filename.chars().collect();
+            for gram in chars.windows(n) {
+                let postings = index.entry(gram.iter().collect()).or_default();
+                // Elements are visited in order, so a repeated gram can only be the last entry
+                if postings.last() != Some(&i) {
+                    postings.push(i);
+                }
+            }
+        }
+        let index: HashMap<String, CompressedPostingsList> = index
+            .into_iter()
+            .map(|(gram, postings)| (gram, CompressedPostingsList::new(postings)))
+            .collect();
+        let ngram_index = NgramIndex {
+            n,
+            index,
+            delta: DeltaSegment::new(),
+            num_elements: tree.len(),
+        };
+        println!(
+            "Created {}-gram reverse index with {} entries and {} bytes in {:?}",
+            n,
+            ngram_index.len(),
+            ngram_index.memory_usage(),
+            time_start.elapsed()
+        );
+        ngram_index
+    }
+
+    // Indexes the elements added to the tree since the last call, like BigramIndex::add_elements
+    pub fn add_elements(&mut self, tree: &FileTree) {
+        let new_elements: Vec<ElementId> = (self.num_elements..tree.len()).map(ElementId::new).collect();
+        self.num_elements = self.num_elements.max(tree.len());
+        self.update_elements(tree, &new_elements);
+    }
+
+    // Indexes the current names of the elements, like BigramIndex::update_elements
+    pub fn update_elements(&mut self, tree: &FileTree, indices: &[ElementId]) {
+        for &i in indices {
+            let chars: Vec<char> = tree.get_filename(i).to_lowercase().chars().collect();
+            for gram in chars.windows(self.n) {
+                self.delta.add(gram.iter().collect(), i);
+            }
+        }
+        if self.delta.should_merge() {
+            self.merge_delta();
+        }
+    }
+
+    pub fn merge_delta(&mut self) {
+        for (gram, delta) in self.delta.drain() {
+            let postings = self.index.get(&gram).map(CompressedPostingsList::decompress).unwrap_or_default();
+            self.index.insert(gram, CompressedPostingsList::new(merged_postings(postings, &delta)));
+        }
+    }
+}
+
+impl TextIndex for NgramIndex {
+    fn gram_size(&self) -> usize {
+        self.n
+    }
+
+    fn candidates(&self, text: &str) -> Vec<ElementId> {
+        let chars: Vec<char> = text.chars().collect();
+        let mut grams: Vec<String> = chars.windows(self.n).map(|gram| gram.iter().collect()).collect();
+        grams.sort();
+        grams.dedup();
+
+        let mut postings_lists = Vec::with_capacity(grams.len());
+        for gram in &grams {
+            match (self.index.get(gram), self.delta.get(gram)) {
+                (None, None) => return Vec::new(),
+                (Some(postings_list), None) => postings_lists.push(Cow::Borrowed(postings_list)),
+                (postings_list, delta) => {
+                    let postings = postings_list.map(CompressedPostingsList::decompress).unwrap_or_default();
+                    let merged = merged_postings(postings, delta.unwrap_or_default());
+                    postings_lists.push(Cow::Owned(CompressedPostingsList::new(merged)));
+                }
+            }
+        }
+        // Intersect starting with the shortest list, which keeps the intermediate results small.
+        // The longer lists are only decoded around the remaining candidates.
+        postings_lists.sort_by_key(|postings_list| postings_list.length);
+        let mut lists = postings_lists.iter();
+        let Some(first) = lists.next() else {
+            return Vec::new();
+        };
+        let mut indices = first.decompress();
+        for postings_list in lists {
+            if indices.is_empty() {
+                break;
+            }
+            indices = postings_list.intersect_sorted(&indices);
+        }
+        indices
+    }
+
+    fn len(&self) -> usize {
+        self.index.len() + self.delta.keys().filter(|gram| !self.index.contains_key(*gram)).count()
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.index.values().map(CompressedPostingsList::memory_usage).sum::<usize>()
+            + self.index.keys().map(String::capacity).sum::<usize>()
+            + self.index.capacity() * std::mem::size_of::<(String, CompressedPostingsList)>()
+            + self.delta.keys().map(String::capacity).sum::<usize>()
+            + self.delta.memory_usage()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ngram_index() {
+        let mut tree = FileTree::with_capacity(10);
+        let photo = tree.add_or_update_recursive("pics/Photo.JPG", None, None, None, 0);
+        let jpeg = tree.add_or_update_recursive("pics/scan.jpeg", None, None, None, 0);
+        let notes = tree.add_or_update_recursive("docs/notes.txt", None, None, None, 0);
+        let bigrams = BigramIndex::new(&tree);
+
+        for n in 2..=4 {
+            let ngrams = NgramIndex::new(&tree, n);
+            assert_eq!(ngrams.gram_size(), n);
+            assert!(ngrams.memory_usage() > 0);
+            for query in [".jpg", "jpe", "photo", "s.t", "notes.txt", "xyz"] {
+                if query.chars().count() < n {
+                    continue;
+                }
+                // Never miss a match, and never return more than the bigrams
+                let candidates = ngrams.candidates(query);
+                let matches: Vec<ElementId> = tree
+                    .ids()
+                    .filter(|&index| tree.get_filename(index).to_lowercase().contains(query))
+                    .collect();
+                assert!(matches.iter().all(|index| candidates.contains(index)), "{}-grams of {}", n, query);
+                assert!(candidates.iter().all(|index| bigrams.candidates(query).contains(index)));
+            }
+        }
+
+        let trigrams = NgramIndex::new(&tree, 3);
+        assert_eq!(trigrams.candidates(".jpg"), vec![photo]);
+        assert_eq!(trigrams.candidates("jpe"), vec![jpeg]);
+        assert_eq!(trigrams.candidates("s.txt"), vec![notes]);
+        assert_eq!(trigrams.candidates("xyz"), Vec::new());
+        // All bigrams of "pho.jpg" occur in photo.jpg, the trigram "ho." doesn't
+        assert_eq!(bigrams.candidates("pho.jpg"), vec![photo]);
+        assert_eq!(trigrams.candidates("pho.jpg"), Vec::new());
+
+        // Bigrams only in the delta segment count as well
+        let mut bigrams = bigrams;
+        let len = TextIndex::len(&bigrams);
+        tree.add_or_update_recursive("pics/zzz.qq", None, None, None, 0);
+        bigrams.add_elements(&tree);
+        assert!(TextIndex::len(&bigrams) > len);
+        assert_eq!(TextIndex::len(&bigrams), bigrams.len());
+    }
+}
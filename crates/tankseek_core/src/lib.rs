@@ -1,6 +1,9 @@
 pub mod file_tree;
+mod element_store;
+pub mod file_type;
 pub mod indexer;
 pub mod loader;
+pub mod memory;
 pub mod post_filter;
 pub mod searcher;
 pub mod sorter;
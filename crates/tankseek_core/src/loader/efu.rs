@@ -27,6 +27,8 @@ pub fn import_efu<P: AsRef<Path>>(filepath: P) -> Result<FileTree, Box<dyn Error
     let estimated_records = (file_size / 100) as usize;
     // List of elements to build the tree structure
     let mut tree: FileTree = FileTree::with_capacity(estimated_records);
+    // Names like index.js occur thousands of times, store each of them once
+    tree.start_interning();
 
     // Create a CSV reader from the file
     let mut rdr = csv::Reader::from_reader(file_list_reader);
@@ -45,8 +47,37 @@ pub fn import_efu<P: AsRef<Path>>(filepath: P) -> Result<FileTree, Box<dyn Error
         // println!("Added file: {}", record.filename);
     }
 
-    // Reduce capacity to the actual number of elements
-    tree.shrink_to_fit();
+    tree.stop_interning();
+    // Switch to the compact layout, which also reduces capacity to the actual number of elements
+    tree.freeze();
+    tree.compute_intervals();
     // Return the elements as a vector
     Ok(tree)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_import_efu() {
+        let path = std::env::temp_dir().join(format!("tankseek_test_import_{}.efu", std::process::id()));
+        std::fs::write(
+            &path,
+            "Filename,Size,Date Modified,Date Created,Attributes\n\
+             C:\\docs\\index.js,12,133600000000000000,,32\n\
+             C:\\src\\index.js,,,,32\n",
+        )
+        .unwrap();
+        let tree = import_efu(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(tree.is_frozen());
+        assert!(tree.has_intervals());
+        let first = tree.find_path("C:\\docs\\index.js").unwrap();
+        let second = tree.find_path("C:\\src\\index.js").unwrap();
+        assert_eq!((tree.size(first), tree.size(second)), (Some(12), None));
+        // Both share the interned name
+        assert_eq!(tree.filename(first), tree.filename(second));
+    }
+}
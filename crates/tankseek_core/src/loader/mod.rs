@@ -1 +1,2 @@
 pub mod efu;
+pub mod snapshot;
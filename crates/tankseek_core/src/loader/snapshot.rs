@@ -0,0 +1,558 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+use memmap2::Mmap;
+
+use crate::file_tree::{Element, ElementId, FileTree, Filename, RawId};
+use crate::indexer::bigram_index::{Bigram, BigramIndex, CompressedPostingsList};
+use crate::indexer::postings::{BITMAP_WORDS, BitmapPostings, Container, Postings};
+use crate::loader::efu::import_efu;
+use crate::searcher::Searcher;
+use crate::sorter::Sorter;
+
+// Binary snapshot of the file tree, the bigram index and the sort orders, so a restart doesn't
+// have to parse the EFU again. All numbers are little endian. Layout:
+//   header: magic, version, crc32 of the body, source length, source modification time, body length
+//   body:   string buffer, elements, children, removed elements, bigram and character postings
+//           (variable byte lists or bitmaps), the four sort orders
+const MAGIC: &[u8; 8] = b"TSKSNAP\0";
+// Increase whenever the layout changes, older snapshots are then rebuilt
+pub const SNAPSHOT_VERSION: u32 = 4;
+const HEADER_LEN: usize = 8 + 4 + 4 + 8 + 8 + 8;
+// Stands in for None in the optional size and date fields
+const NONE_I64: i64 = i64::MIN;
+
+// Identifies the EFU file a snapshot was built from, a different stamp means the snapshot is stale
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourceStamp {
+    pub len: u64,
+    pub modified: u64, // Nanoseconds since the unix epoch
+}
+impl SourceStamp {
+    pub fn of<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        let metadata = std::fs::metadata(path)?;
+        let modified = metadata.modified()?.duration_since(UNIX_EPOCH)?.as_nanos() as u64;
+        Ok(SourceStamp {
+            len: metadata.len(),
+            modified,
+        })
+    }
+}
+
+// Loads the snapshot if it matches the EFU file, otherwise imports the EFU and writes a new snapshot.
+// The index is rebuilt with the given number of threads.
+pub fn load_or_rebuild<P: AsRef<Path>, Q: AsRef<Path>>(
+    efu_path: P,
+    snapshot_path: Q,
+    threads: usize,
+) -> Result<Searcher, Box<dyn Error>> {
+    let snapshot_path = snapshot_path.as_ref();
+    let stamp = SourceStamp::of(&efu_path)?;
+    if snapshot_path.exists() {
+        let time_start = std::time::Instant::now();
+        match read_snapshot(snapshot_path, &stamp) {
+            Ok(searcher) => {
+                println!(
+                    "Loaded snapshot {} with {} elements in {:?}",
+                    snapshot_path.display(),
+                    searcher.file_tree.len(),
+                    time_start.elapsed()
+                );
+                return Ok(searcher);
+            }
+            Err(e) => println!("Rebuilding snapshot {}: {}", snapshot_path.display(), e),
+        }
+    }
+
+    let searcher = Searcher::from_file_tree_with_threads(import_efu(&efu_path)?, threads);
+    searcher.sorter.prepare_all(&searcher.file_tree);
+    // Failing to write the snapshot only costs the next start some time
+    if let Err(e) = write_snapshot(snapshot_path, &searcher, &stamp) {
+        eprintln!("Error writing snapshot {}: {}", snapshot_path.display(), e);
+    }
+    Ok(searcher)
+}
+
+// Writes the snapshot to a temporary file first, so a crash never leaves a half written snapshot
+pub fn write_snapshot<P: AsRef<Path>>(
+    path: P,
+    searcher: &Searcher,
+    source: &SourceStamp,
+) -> Result<(), Box<dyn Error>> {
+    let path = path.as_ref();
+    let time_start = std::time::Instant::now();
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+
+    let mut file = File::create(&tmp_path)?;
+    // The checksum and body length are filled in after the body is written
+    file.write_all(&[0; HEADER_LEN])?;
+    let mut writer = ChecksumWriter {
+        inner: BufWriter::new(file),
+        hasher: crc32fast::Hasher::new(),
+        written: 0,
+    };
+    write_body(&mut writer, searcher)?;
+    let ChecksumWriter {
+        inner,
+        hasher,
+        written,
+    } = writer;
+    let mut file = inner.into_inner().map_err(|e| e.into_error())?;
+
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    header.extend_from_slice(MAGIC);
+    header.extend_from_slice(&SNAPSHOT_VERSION.to_le_bytes());
+    header.extend_from_slice(&hasher.finalize().to_le_bytes());
+    header.extend_from_slice(&source.len.to_le_bytes());
+    header.extend_from_slice(&source.modified.to_le_bytes());
+    header.extend_from_slice(&written.to_le_bytes());
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(&header)?;
+    file.sync_all()?;
+    drop(file);
+    std::fs::rename(&tmp_path, path)?;
+
+    println!(
+        "Wrote snapshot {} with {} bytes in {:?}",
+        path.display(),
+        HEADER_LEN as u64 + written,
+        time_start.elapsed()
+    );
+    Ok(())
+}
+
+fn write_body<W: Write>(w: &mut W, searcher: &Searcher) -> std::io::Result<()> {
+    let tree = &searcher.file_tree;
+    let strbuf = tree.strbuf();
+    write_u64(w, strbuf.len() as u64)?;
+    w.write_all(strbuf)?;
+
+    write_u64(w, tree.len() as u64)?;
+    for index in tree.ids() {
+        let (start, end) = tree.filename(index).bounds();
+        write_u64(w, start as u64)?;
+        write_u64(w, end as u64)?;
+        write_i64(w, tree.size(index).unwrap_or(NONE_I64))?;
+        write_i64(w, tree.date_modified(index).unwrap_or(NONE_I64))?;
+        write_i64(w, tree.date_created(index).unwrap_or(NONE_I64))?;
+        write_u32(w, tree.attributes(index))?;
+        write_u64(w, tree.parent(index).index() as u64)?;
+    }
+    for index in tree.ids() {
+        write_indices(w, tree.children(index).iter().map(|child| child.index()))?;
+    }
+    let removed: Vec<usize> = tree.ids().filter(|&index| tree.is_removed(index)).map(ElementId::index).collect();
+    write_indices(w, removed.into_iter())?;
+
+    let bigram_index = &searcher.bigram_index;
+    write_u64(w, bigram_index.num_elements() as u64)?;
+    write_u64(w, bigram_index.len() as u64)?;
+    for (bigram, postings_list) in bigram_index.postings_lists() {
+        write_u32(w, bigram.first as u32)?;
+        write_u32(w, bigram.second as u32)?;
+        write_postings(w, &postings_list)?;
+    }
+    let unigrams: Vec<_> = bigram_index.unigram_lists().collect();
+    write_u64(w, unigrams.len() as u64)?;
+    for (c, postings_list) in &unigrams {
+        write_u32(w, *c as u32)?;
+        write_postings(w, postings_list)?;
+    }
+
+    let sorter = &searcher.sorter;
+    sorter.apply_changes(&searcher.file_tree);
+    for order in [
+        &sorter.filename_order,
+        &sorter.date_modified_order,
+        &sorter.date_created_order,
+        &sorter.size_order,
+    ] {
+        match order.lock().unwrap().as_ref() {
+            Some(order) => {
+                w.write_all(&[1])?;
+                write_indices(w, order.iter().map(|&rank| rank as usize))?;
+            }
+            None => w.write_all(&[0])?,
+        }
+    }
+    Ok(())
+}
+
+// Maps the snapshot into memory and rebuilds the searcher from it. Fails if the snapshot is from
+// another version, corrupt, or was built from a different EFU file.
+pub fn read_snapshot<P: AsRef<Path>>(path: P, source: &SourceStamp) -> Result<Searcher, Box<dyn Error>> {
+    let file = File::open(path)?;
+    // SAFETY: The mapping is only read while parsing and everything is copied out of it.
+    // Snapshots are replaced by renaming, so the mapped file itself is not modified.
+    let mmap = unsafe { Mmap::map(&file)? };
+    if mmap.len() < HEADER_LEN || &mmap[..8] != MAGIC {
+        return Err("not a snapshot file".into());
+    }
+    let mut header = Reader::new(&mmap[8..HEADER_LEN]);
+    let version = header.u32()?;
+    if version != SNAPSHOT_VERSION {
+        return Err(format!("snapshot version {} is not supported", version).into());
+    }
+    let checksum = header.u32()?;
+    let stamp = SourceStamp {
+        len: header.u64()?,
+        modified: header.u64()?,
+    };
+    if stamp != *source {
+        return Err("snapshot is stale".into());
+    }
+    let body = &mmap[HEADER_LEN..];
+    if header.u64()? != body.len() as u64 {
+        return Err("snapshot is truncated".into());
+    }
+    if crc32fast::hash(body) != checksum {
+        return Err("snapshot checksum mismatch".into());
+    }
+    read_body(&mut Reader::new(body))
+}
+
+fn read_body(r: &mut Reader) -> Result<Searcher, Box<dyn Error>> {
+    let strbuf_len = r.usize()?;
+    let strbuf = r.bytes(strbuf_len)?.to_vec();
+
+    let num_elements = r.usize()?;
+    if RawId::try_from(num_elements.saturating_sub(1)).is_err() {
+        return Err("snapshot has more elements than fit into element ids".into());
+    }
+    let mut elements = Vec::with_capacity(num_elements);
+    for _ in 0..num_elements {
+        let start = r.usize()?;
+        let end = r.usize()?;
+        // get_filename relies on valid UTF-8 ranges
+        if start > end || end > strbuf.len() || std::str::from_utf8(&strbuf[start..end]).is_err() {
+            return Err("invalid filename in snapshot".into());
+        }
+        let optional = |value: i64| (value != NONE_I64).then_some(value);
+        elements.push(Element {
+            filename: Filename::new(start, end),
+            size: optional(r.i64()?),
+            date_modified: optional(r.i64()?),
+            date_created: optional(r.i64()?),
+            attributes: r.u32()?,
+            parent: r.id(num_elements)?,
+            children: Vec::new(),
+        });
+    }
+    for element in &mut elements {
+        element.children = r.ids(num_elements)?;
+    }
+    if elements.is_empty() {
+        return Err("snapshot has no root element".into());
+    }
+    let removed = r.ids(num_elements)?;
+    let mut tree = FileTree::from_parts(elements, strbuf);
+    for index in removed {
+        tree.mark_removed(index);
+    }
+    tree.freeze();
+
+    let index_elements = r.usize()?;
+    let num_bigrams = r.usize()?;
+    let mut index = HashMap::with_capacity(num_bigrams.min(r.remaining()));
+    for _ in 0..num_bigrams {
+        let bigram = Bigram {
+            first: r.char()?,
+            second: r.char()?,
+        };
+        index.insert(bigram, read_postings(r)?);
+    }
+    let num_unigrams = r.usize()?;
+    let mut unigrams = HashMap::with_capacity(num_unigrams.min(r.remaining()));
+    for _ in 0..num_unigrams {
+        unigrams.insert(r.char()?, read_postings(r)?);
+    }
+    let bigram_index = BigramIndex::from_parts(index, unigrams, index_elements);
+
+    let mut orders = Vec::with_capacity(4);
+    for _ in 0..4 {
+        let order = match r.bytes(1)?[0] {
+            0 => None,
+            _ => Some(r.ranks(num_elements)?),
+        };
+        if order.as_ref().is_some_and(|order| order.len() != num_elements) {
+            return Err("sort order does not match the number of elements".into());
+        }
+        orders.push(Mutex::new(order));
+    }
+    let mut orders = orders.into_iter();
+    let mut next_order = || orders.next().unwrap();
+    let sorter = Sorter {
+        filename_order: next_order(),
+        date_modified_order: next_order(),
+        date_created_order: next_order(),
+        size_order: next_order(),
+        changed: Mutex::new(Vec::new()),
+    };
+    if r.remaining() != 0 {
+        return Err("unexpected data at the end of the snapshot".into());
+    }
+    Ok(Searcher::from_parts(tree, bigram_index, sorter))
+}
+
+// A tag byte for the kind, then the variable byte list or the bitmap chunks
+fn write_postings<W: Write>(w: &mut W, postings: &Postings) -> std::io::Result<()> {
+    match postings {
+        Postings::VByte(list) => {
+            w.write_all(&[0])?;
+            write_u64(w, list.length as u64)?;
+            write_u64(w, list.indices.len() as u64)?;
+            w.write_all(&list.indices)
+        }
+        Postings::Bitmap(bitmap) => {
+            w.write_all(&[1])?;
+            write_u64(w, bitmap.keys.len() as u64)?;
+            for (&key, container) in bitmap.keys.iter().zip(&bitmap.containers) {
+                write_u64(w, key as u64)?;
+                match container {
+                    Container::Array(lows) => {
+                        w.write_all(&[0])?;
+                        write_u64(w, lows.len() as u64)?;
+                        for &low in lows {
+                            w.write_all(&low.to_le_bytes())?;
+                        }
+                    }
+                    Container::Bitmap(words) => {
+                        w.write_all(&[1])?;
+                        for &word in words.iter() {
+                            write_u64(w, word)?;
+                        }
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+fn read_postings(r: &mut Reader) -> Result<Postings, Box<dyn Error>> {
+    match r.bytes(1)?[0] {
+        0 => {
+            let length = r.usize()?;
+            let bytes_len = r.usize()?;
+            let indices = r.bytes(bytes_len)?.to_vec();
+            Ok(Postings::VByte(CompressedPostingsList::from_encoded(indices, length)))
+        }
+        1 => {
+            let num_containers = r.usize()?;
+            let mut keys: Vec<usize> = Vec::with_capacity(num_containers.min(r.remaining()));
+            let mut containers = Vec::with_capacity(keys.capacity());
+            for _ in 0..num_containers {
+                let key = r.usize()?;
+                // Lookups rely on sorted keys and array containers
+                if keys.last().is_some_and(|&last| last >= key) {
+                    return Err("unsorted bitmap in snapshot".into());
+                }
+                keys.push(key);
+                let container = match r.bytes(1)?[0] {
+                    0 => {
+                        let len = r.usize()?;
+                        let lows: Vec<u16> = r
+                            .bytes(len.checked_mul(2).ok_or("bitmap too large")?)?
+                            .chunks_exact(2)
+                            .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+                            .collect();
+                        if !lows.is_sorted() {
+                            return Err("unsorted bitmap in snapshot".into());
+                        }
+                        Container::Array(lows)
+                    }
+                    _ => {
+                        let mut words = Vec::with_capacity(BITMAP_WORDS);
+                        for _ in 0..BITMAP_WORDS {
+                            words.push(r.u64()?);
+                        }
+                        Container::Bitmap(words.into_boxed_slice())
+                    }
+                };
+                containers.push(container);
+            }
+            Ok(Postings::Bitmap(BitmapPostings::from_containers(keys, containers)))
+        }
+        kind => Err(format!("unknown postings kind {} in snapshot", kind).into()),
+    }
+}
+
+fn write_u32<W: Write>(w: &mut W, value: u32) -> std::io::Result<()> {
+    w.write_all(&value.to_le_bytes())
+}
+fn write_u64<W: Write>(w: &mut W, value: u64) -> std::io::Result<()> {
+    w.write_all(&value.to_le_bytes())
+}
+fn write_i64<W: Write>(w: &mut W, value: i64) -> std::io::Result<()> {
+    w.write_all(&value.to_le_bytes())
+}
+fn write_indices<W: Write>(w: &mut W, indices: impl ExactSizeIterator<Item = usize>) -> std::io::Result<()> {
+    write_u64(w, indices.len() as u64)?;
+    for index in indices {
+        write_u64(w, index as u64)?;
+    }
+    Ok(())
+}
+
+// Passes everything through to the inner writer while computing the checksum and length
+struct ChecksumWriter<W: Write> {
+    inner: W,
+    hasher: crc32fast::Hasher,
+    written: u64,
+}
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.written += n as u64;
+        Ok(n)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// Bounds checked cursor over the mapped bytes
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Reader { data, pos: 0 }
+    }
+    fn remaining(&self) -> usize {
+        self.data.len() - self.pos
+    }
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], Box<dyn Error>> {
+        if len > self.remaining() {
+            return Err("snapshot ends unexpectedly".into());
+        }
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], Box<dyn Error>> {
+        Ok(self.bytes(N)?.try_into().unwrap())
+    }
+    fn u32(&mut self) -> Result<u32, Box<dyn Error>> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+    fn u64(&mut self) -> Result<u64, Box<dyn Error>> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+    fn i64(&mut self) -> Result<i64, Box<dyn Error>> {
+        Ok(i64::from_le_bytes(self.array()?))
+    }
+    fn usize(&mut self) -> Result<usize, Box<dyn Error>> {
+        Ok(usize::try_from(self.u64()?)?)
+    }
+    fn char(&mut self) -> Result<char, Box<dyn Error>> {
+        char::from_u32(self.u32()?).ok_or_else(|| "invalid character in snapshot".into())
+    }
+    // An element index, which has to be below the number of elements
+    fn index(&mut self, num_elements: usize) -> Result<usize, Box<dyn Error>> {
+        let index = self.usize()?;
+        if index >= num_elements {
+            return Err("element index out of range in snapshot".into());
+        }
+        Ok(index)
+    }
+    fn id(&mut self, num_elements: usize) -> Result<ElementId, Box<dyn Error>> {
+        Ok(ElementId::new(self.index(num_elements)?))
+    }
+    fn ids(&mut self, num_elements: usize) -> Result<Vec<ElementId>, Box<dyn Error>> {
+        self.list(|r| r.id(num_elements))
+    }
+    // The ranks of a sort order, also below the number of elements
+    fn ranks(&mut self, num_elements: usize) -> Result<Vec<RawId>, Box<dyn Error>> {
+        self.list(|r| Ok(r.index(num_elements)? as RawId))
+    }
+    fn list<T>(
+        &mut self,
+        read: impl Fn(&mut Self) -> Result<T, Box<dyn Error>>,
+    ) -> Result<Vec<T>, Box<dyn Error>> {
+        let len = self.usize()?;
+        // Each entry takes 8 bytes, so a corrupt length can't allocate more than the file size
+        let mut list = Vec::with_capacity(len.min(self.remaining() / 8));
+        for _ in 0..len {
+            list.push(read(self)?);
+        }
+        Ok(list)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sorter::{SortField, SortOrder};
+
+    #[test]
+    fn test_snapshot_roundtrip() {
+        let dir = std::env::temp_dir().join(format!("tankseek_test_snapshot_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let efu_path = dir.join("filelist.efu");
+        let snapshot_path = dir.join("filelist.snapshot");
+        std::fs::write(
+            &efu_path,
+            "Filename,Size,Date Modified,Date Created,Attributes\n\
+             C:\\docs,,,,16\n\
+             C:\\docs\\Report.pdf,2048,133500000000000000,133400000000000000,32\n\
+             C:\\docs\\notes.txt,12,133600000000000000,,32\n\
+             C:\\music\\Song ä.mp3,4000000,,,32\n",
+        )
+        .unwrap();
+
+        // The first load builds the snapshot, the second one reads it
+        let built = load_or_rebuild(&efu_path, &snapshot_path, 2).unwrap();
+        assert!(snapshot_path.exists());
+        let stamp = SourceStamp::of(&efu_path).unwrap();
+        let loaded = read_snapshot(&snapshot_path, &stamp).unwrap();
+
+        assert_eq!(loaded.file_tree.len(), built.file_tree.len());
+        for index in built.file_tree.ids() {
+            let (a, b) = (built.get(index).unwrap(), loaded.get(index).unwrap());
+            assert_eq!(loaded.file_tree.get_full_path(index), built.file_tree.get_full_path(index));
+            assert_eq!((a.size(), a.date_modified()), (b.size(), b.date_modified()));
+            assert_eq!(a.date_created(), b.date_created());
+            assert_eq!((a.attributes(), a.parent(), a.children()), (b.attributes(), b.parent(), b.children()));
+        }
+        assert!(loaded.file_tree.has_intervals());
+        assert_eq!(loaded.bigram_index.len(), built.bigram_index.len());
+        assert_eq!(loaded.bigram_index.unigrams.len(), built.bigram_index.unigrams.len());
+        assert!(loaded.sorter.size_order.lock().unwrap().is_some());
+        for query in ["report", "ä", "ext:pdf;mp3", "path:docs", "s"] {
+            for sort_by in [SortField::Filename, SortField::Size, SortField::DateModified] {
+                assert_eq!(
+                    loaded.search(query, Some(sort_by), Some(SortOrder::Ascending)),
+                    built.search(query, Some(sort_by), Some(SortOrder::Ascending)),
+                    "{} sorted by {:?}",
+                    query,
+                    sort_by
+                );
+            }
+        }
+
+        // A different source or a damaged snapshot is rejected
+        let other = SourceStamp { len: stamp.len + 1, ..stamp };
+        assert!(read_snapshot(&snapshot_path, &other).is_err());
+        let mut bytes = std::fs::read(&snapshot_path).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xFF;
+        std::fs::write(&snapshot_path, &bytes).unwrap();
+        let error = read_snapshot(&snapshot_path, &stamp).err().unwrap();
+        assert_eq!(error.to_string(), "snapshot checksum mismatch");
+        // which is then rebuilt from the EFU
+        let rebuilt = load_or_rebuild(&efu_path, &snapshot_path, 1).unwrap();
+        assert_eq!(rebuilt.file_tree.len(), built.file_tree.len());
+        assert!(read_snapshot(&snapshot_path, &stamp).is_ok());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
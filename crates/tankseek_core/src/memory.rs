@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::Serialize;
+
+// Approximate heap usage of a structure, broken down into its parts. The bytes of a structure
+// are the sum of its parts.
+#[derive(Debug, Clone, Serialize)]
+pub struct MemoryUsage {
+    pub name: &'static str,
+    pub bytes: usize,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub parts: Vec<MemoryUsage>,
+}
+impl MemoryUsage {
+    pub fn new(name: &'static str) -> Self {
+        MemoryUsage {
+            name,
+            bytes: 0,
+            parts: Vec::new(),
+        }
+    }
+
+    // Adds a part without further breakdown
+    pub fn with(self, name: &'static str, bytes: usize) -> Self {
+        self.with_part(MemoryUsage {
+            name,
+            bytes,
+            parts: Vec::new(),
+        })
+    }
+    pub fn with_part(mut self, part: MemoryUsage) -> Self {
+        self.bytes += part.bytes;
+        self.parts.push(part);
+        self
+    }
+
+    // The part with the given name, searching nested parts as well
+    pub fn get(&self, name: &str) -> Option<&MemoryUsage> {
+        self.parts
+            .iter()
+            .find_map(|part| if part.name == name { Some(part) } else { part.get(name) })
+    }
+
+    fn fmt_indented(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        writeln!(f, "{:indent$}{:<24} {:>12} KiB", "", self.name, self.bytes / 1024, indent = depth * 2)?;
+        for part in &self.parts {
+            part.fmt_indented(f, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+impl fmt::Display for MemoryUsage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_indented(f, 0)
+    }
+}
+
+// Heap bytes of the allocated capacity of a vector
+pub fn vec_bytes<T>(vec: &Vec<T>) -> usize {
+    vec.capacity() * std::mem::size_of::<T>()
+}
+
+// Heap bytes of the table of a hash map: the entries and one control byte per slot,
+// not counting memory the keys and values own themselves
+pub fn hash_map_bytes<K, V>(map: &HashMap<K, V>) -> usize {
+    map.capacity() * (std::mem::size_of::<(K, V)>() + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_usage() {
+        let index = MemoryUsage::new("index").with("postings", 100).with("table", 20);
+        let usage = MemoryUsage::new("searcher").with("strbuf", 5).with_part(index);
+        assert_eq!(usage.bytes, 125);
+        assert_eq!(usage.get("index").unwrap().bytes, 120);
+        assert_eq!(usage.get("table").unwrap().bytes, 20);
+        assert!(usage.get("missing").is_none());
+        assert_eq!(vec_bytes(&Vec::<u32>::with_capacity(4)), 16);
+
+        let json = serde_json::to_string(&usage).unwrap();
+        assert!(json.starts_with(r#"{"name":"searcher","bytes":125,"parts":[{"name":"strbuf","bytes":5}"#));
+        assert!(usage.to_string().contains("    postings"));
+    }
+}
@@ -1,8 +1,8 @@
 // use std::time::Instant;
 
-use crate::file_tree::FileTree;
+use crate::file_tree::{ElementId, FileTree};
 
-pub fn post_filter(tree: &FileTree, indices: &mut Vec<usize>, query: &str) {
+pub fn post_filter(tree: &FileTree, indices: &mut Vec<ElementId>, query: &str) {
     // let start_time = Instant::now();
     // let original_len = indices.len();
 
@@ -13,7 +13,7 @@ pub fn post_filter(tree: &FileTree, indices: &mut Vec<usize>, query: &str) {
 
     // Filter results based on the query
 
-    indices.retain(|&index| regex.is_match(&tree.get_filename(index)));
+    indices.retain(|&index| regex.is_match(tree.get_filename(index)));
 
     // print!(
     //     "Post-filtering took {} ms, reduced results from {} to {}\n",
@@ -0,0 +1,266 @@
+use serde::{Deserialize, Serialize};
+
+use crate::file_tree::FileTree;
+use crate::query::macros::QueryMacro;
+use crate::query::query_parser::SIZE_KEYWORDS;
+
+// Function names understood by parse_function
+pub const FUNCTION_NAMES: &[&str] = &[
+    "size", "datemodified", "dm", "datecreated", "dc", "parent", "infolder", "nosubfolders", "ext",
+    "startwith", "child", "descendant", "audio", "video", "pic", "doc", "exe", "zip",
+];
+
+// Modifier names understood by parse_modifier
+pub const MODIFIER_NAMES: &[&str] = &[
+    "case", "nocase", "diacritics", "nodiacritics", "file", "files", "nofileonly", "folder",
+    "folders", "nofolderonly", "path", "nopath", "regex", "noregex", "wholefilename", "wfn",
+    "exact", "nowfn", "nowholefilename", "wholeword", "ww", "nowholeword", "noww", "wildcards",
+    "nowildcards", "fuzzy", "nofuzzy",
+];
+
+// Common date keywords, see QueryDate::parse for all supported forms
+pub const DATE_KEYWORDS: &[&str] = &[
+    "today", "yesterday", "thisweek", "thismonth", "thisquarter", "thisyear", "lastweek",
+    "lastmonth", "lastquarter", "lastyear", "last7days", "last30days", "unknown",
+];
+
+const DATE_FUNCTIONS: &[&str] = &["datemodified", "dm", "datecreated", "dc"];
+const FOLDER_FUNCTIONS: &[&str] = &["parent", "infolder", "nosubfolders"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CompletionKind {
+    Function,
+    Modifier,
+    Macro,
+    Size,
+    Date,
+    Folder,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Suggestion {
+    pub text: String,
+    pub kind: CompletionKind,
+}
+
+// Suggestions replace the characters start..end of the query
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Completions {
+    pub start: usize,
+    pub end: usize,
+    pub suggestions: Vec<Suggestion>,
+}
+
+// Suggests how to continue the word at the cursor (a character position in the query)
+pub fn complete(
+    query: &str,
+    cursor: usize,
+    tree: &FileTree,
+    macros: &[QueryMacro],
+    limit: usize,
+) -> Completions {
+    let chars: Vec<char> = query.chars().collect();
+    let cursor = cursor.min(chars.len());
+
+    // The word at the cursor starts after the last whitespace outside of quotes
+    let mut start = 0;
+    let mut in_quotes = false;
+    for (position, &c) in chars[..cursor].iter().enumerate() {
+        if c == '"' {
+            in_quotes = !in_quotes;
+        } else if c.is_whitespace() && !in_quotes {
+            start = position + 1;
+        }
+    }
+    while start < cursor && matches!(chars[start], '!' | '<') {
+        start += 1;
+    }
+
+    // Skip over modifiers like case: and find what is being typed
+    loop {
+        let word: String = chars[start..cursor].iter().collect();
+        let Some((name, argument)) = word.split_once(':') else {
+            let suggestions = complete_name(&word, macros, limit);
+            return Completions {
+                start,
+                end: cursor,
+                suggestions,
+            };
+        };
+        let argument_start = start + name.chars().count() + 1;
+        let name = name.to_lowercase();
+        if MODIFIER_NAMES.contains(&name.as_str()) {
+            start = argument_start;
+            continue;
+        }
+
+        let (offset, suggestions) = if name == "size" {
+            // Keep a comparison like > in front of the keyword
+            let operator_len = argument.len() - argument.trim_start_matches(['<', '>', '=']).len();
+            let suggestions = complete_from(
+                SIZE_KEYWORDS.iter().map(|(keyword, _, _)| *keyword),
+                &argument[operator_len..],
+                CompletionKind::Size,
+                limit,
+            );
+            (operator_len, suggestions)
+        } else if DATE_FUNCTIONS.contains(&name.as_str()) {
+            let operator_len = argument.len() - argument.trim_start_matches(['<', '>', '=']).len();
+            let suggestions = complete_from(
+                DATE_KEYWORDS.iter().copied(),
+                &argument[operator_len..],
+                CompletionKind::Date,
+                limit,
+            );
+            (operator_len, suggestions)
+        } else if FOLDER_FUNCTIONS.contains(&name.as_str()) {
+            match argument.strip_prefix('"') {
+                Some(quoted) => (1, complete_folder(tree, quoted, false, limit)),
+                None => (0, complete_folder(tree, argument, true, limit)),
+            }
+        } else {
+            (0, Vec::new())
+        };
+        return Completions {
+            start: argument_start + offset,
+            end: cursor,
+            suggestions,
+        };
+    }
+}
+
+// Function, modifier and macro names starting with the prefix
+fn complete_name(prefix: &str, macros: &[QueryMacro], limit: usize) -> Vec<Suggestion> {
+    let names = FUNCTION_NAMES
+        .iter()
+        .map(|name| (name.to_string(), CompletionKind::Function))
+        .chain(MODIFIER_NAMES.iter().map(|name| (name.to_string(), CompletionKind::Modifier)))
+        .chain(macros.iter().map(|query_macro| (query_macro.name.clone(), CompletionKind::Macro)));
+    let prefix = prefix.to_lowercase();
+    names
+        .filter(|(name, _)| !prefix.is_empty() && name.to_lowercase().starts_with(&prefix))
+        .take(limit)
+        .map(|(name, kind)| Suggestion {
+            text: format!("{}:", name),
+            kind,
+        })
+        .collect()
+}
+
+fn complete_from<'a>(
+    candidates: impl Iterator<Item = &'a str>,
+    prefix: &str,
+    kind: CompletionKind,
+    limit: usize,
+) -> Vec<Suggestion> {
+    let prefix = prefix.to_lowercase();
+    candidates
+        .filter(|candidate| candidate.starts_with(&prefix))
+        .take(limit)
+        .map(|candidate| Suggestion {
+            text: candidate.to_string(),
+            kind,
+        })
+        .collect()
+}
+
+// Subfolders of the folder typed so far whose name starts with the last path component
+fn complete_folder(tree: &FileTree, path: &str, quote_spaces: bool, limit: usize) -> Vec<Suggestion> {
+    let (folder_path, prefix) = match path.rfind(['\\', '/']) {
+        Some(separator) => path.split_at(separator + 1),
+        None => ("", path),
+    };
+    let Some(folder) = tree.find_path(folder_path) else {
+        return Vec::new();
+    };
+    let prefix = prefix.to_lowercase();
+    tree.children(folder)
+        .iter()
+        .filter(|&&child| tree.is_folder(child))
+        .map(|&child| tree.get_filename(child))
+        .filter(|name| name.to_lowercase().starts_with(&prefix))
+        .take(limit)
+        .map(|name| {
+            let text = format!("{}{}\\", folder_path, name);
+            Suggestion {
+                text: if quote_spaces && text.contains(char::is_whitespace) {
+                    format!("\"{}\"", text)
+                } else {
+                    text
+                },
+                kind: CompletionKind::Folder,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_type::FileCategory;
+    use crate::query::query_parser::{QueryDate, QueryExpr, parse_query};
+
+    fn create_tree() -> FileTree {
+        let mut tree = FileTree::with_capacity(10);
+        tree.add_or_update_recursive("C:\\Windows\\System32\\cmd.exe", Some(10), None, None, 0);
+        tree.add_or_update_recursive("C:\\Program Files\\app\\app.exe", Some(10), None, None, 0);
+        tree.add_or_update_recursive("C:\\pagefile.sys", Some(10), None, None, 0);
+        tree
+    }
+
+    fn suggest(query: &str) -> (usize, Vec<String>) {
+        let tree = create_tree();
+        let macros = vec![QueryMacro::new("src", "ext:rs")];
+        let completions = complete(query, query.chars().count(), &tree, &macros, 10);
+        let texts = completions
+            .suggestions
+            .into_iter()
+            .map(|suggestion| suggestion.text)
+            .collect();
+        (completions.start, texts)
+    }
+
+    #[test]
+    fn test_complete_names() {
+        assert_eq!(suggest("si"), (0, vec!["size:".to_string()]));
+        assert_eq!(suggest("foo !dat"), (5, vec!["datemodified:".to_string(), "datecreated:".to_string()]));
+        assert_eq!(suggest("case:fu"), (5, vec!["fuzzy:".to_string()]));
+        assert_eq!(suggest("SR"), (0, vec!["src:".to_string()]));
+        assert_eq!(suggest("song "), (5, Vec::<String>::new()));
+    }
+
+    #[test]
+    fn test_complete_arguments() {
+        assert_eq!(suggest("size:>l"), (6, vec!["large".to_string()]));
+        assert_eq!(
+            suggest("dm:this").1,
+            vec!["thisweek", "thismonth", "thisquarter", "thisyear"]
+        );
+        assert_eq!(suggest("parent:"), (7, vec!["C:\\".to_string()]));
+        assert_eq!(
+            suggest("parent:c:\\").1,
+            vec!["\"c:\\Program Files\\\"", "c:\\Windows\\"]
+        );
+        assert_eq!(suggest("parent:c:/w"), (7, vec!["c:/Windows\\".to_string()]));
+        assert_eq!(suggest("infolder:\"C:\\Prog"), (10, vec!["C:\\Program Files\\".to_string()]));
+        assert_eq!(suggest("parent:d:\\"), (7, Vec::<String>::new()));
+    }
+
+    #[test]
+    fn test_names_parse() {
+        for name in FUNCTION_NAMES {
+            // Content type macros take no argument
+            let argument = if FileCategory::from_macro(name).is_some() { "" } else { "1" };
+            let query = parse_query(&format!("{}:{}", name, argument));
+            assert!(matches!(query, QueryExpr::Function(_)), "{} is not a function", name);
+        }
+        for name in MODIFIER_NAMES {
+            let query = parse_query(&format!("{}:x", name));
+            assert!(matches!(query, QueryExpr::Literal(_)), "{} is not a modifier", name);
+        }
+        for keyword in DATE_KEYWORDS {
+            assert_ne!(QueryDate::from(*keyword), QueryDate::Range(0, 0), "{} is not a date", keyword);
+        }
+    }
+}
@@ -1,63 +1,378 @@
+use std::sync::LazyLock;
+
+use chrono::{Datelike, Local, Months, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, TimeZone, Timelike};
+
 use crate::query::query_parser::*;
+
+static RELATIVE_REGEX: LazyLock<regex::Regex> = LazyLock::new(|| {
+    regex::Regex::new(r"^(last|past|prev|next|coming)\s*(\d*)\s*(years?|quarters?|months?|weeks?|days?|hours?|minutes?|mins?|seconds?|secs?)$").unwrap()
+});
+static CURRENT_UNIT_REGEX: LazyLock<regex::Regex> = LazyLock::new(|| {
+    regex::Regex::new(r"^(this|current)\s*(year|quarter|month|week|day|hour|minute|min)$").unwrap()
+});
+static QUARTER_REGEX: LazyLock<regex::Regex> =
+    LazyLock::new(|| regex::Regex::new(r"^(?:(\d{4})-?)?q([1-4])$").unwrap());
+static ISO_WEEK_REGEX: LazyLock<regex::Regex> =
+    LazyLock::new(|| regex::Regex::new(r"^(\d{4})-?w(\d{1,2})(?:-?([1-7]))?$").unwrap());
+static AGO_REGEX: LazyLock<regex::Regex> = LazyLock::new(|| {
+    regex::Regex::new(r"^(\d+)\s*(years?|quarters?|months?|weeks?|days?|hours?|minutes?|mins?|seconds?|secs?)\s*ago$").unwrap()
+});
+
+/// How relative dates like `last2months` are interpreted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelativeDateMode {
+    /// A window of n units ending (or starting) now,
+    /// e.g. 2 months on 2024-05-15 14:00 is 2024-03-15 14:00 until now
+    Rolling,
+    /// n whole calendar units before (or after) the current one,
+    /// e.g. 2 months on 2024-05-15 is March and April
+    Calendar,
+}
+
+/// Preferred order of day and month in ambiguous dates like `03/04/2024`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateOrder {
+    /// `03/04/2024` is March 4th
+    MonthFirst,
+    /// `03/04/2024` is April 3rd
+    DayFirst,
+}
+
+/// Options for parsing dates in queries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateOptions {
+    /// Order tried first for ambiguous numeric dates
+    pub date_order: DateOrder,
+    /// Mode for `last`, `prev` and `next`, e.g. `last2months`
+    pub last_mode: RelativeDateMode,
+    /// Mode for `past` and `coming`, e.g. `past2months`
+    pub past_mode: RelativeDateMode,
+}
+impl Default for DateOptions {
+    fn default() -> Self {
+        DateOptions {
+            date_order: DateOrder::MonthFirst,
+            last_mode: RelativeDateMode::Calendar,
+            past_mode: RelativeDateMode::Rolling,
+        }
+    }
+}
+
+// Units of relative dates, ordered from coarse to fine
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum DateUnit {
+    Year,
+    Quarter,
+    Month,
+    Week,
+    Day,
+    Hour,
+    Minute,
+    Second,
+}
+impl DateUnit {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "year" | "years" => Some(DateUnit::Year),
+            "quarter" | "quarters" => Some(DateUnit::Quarter),
+            "month" | "months" => Some(DateUnit::Month),
+            "week" | "weeks" => Some(DateUnit::Week),
+            "day" | "days" => Some(DateUnit::Day),
+            "hour" | "hours" => Some(DateUnit::Hour),
+            "minute" | "minutes" | "min" | "mins" => Some(DateUnit::Minute),
+            "second" | "seconds" | "sec" | "secs" => Some(DateUnit::Second),
+            _ => None,
+        }
+    }
+
+    // Start of the calendar unit containing the datetime, weeks start on Monday
+    fn start_of(self, datetime: NaiveDateTime) -> NaiveDateTime {
+        let date = datetime.date();
+        match self {
+            DateUnit::Year => NaiveDate::from_ymd_opt(date.year(), 1, 1).unwrap().into(),
+            DateUnit::Quarter => {
+                let first_month = (date.month0() / 3) * 3 + 1;
+                NaiveDate::from_ymd_opt(date.year(), first_month, 1).unwrap().into()
+            }
+            DateUnit::Month => NaiveDate::from_ymd_opt(date.year(), date.month(), 1).unwrap().into(),
+            DateUnit::Week => {
+                (date - TimeDelta::days(date.weekday().num_days_from_monday() as i64)).into()
+            }
+            DateUnit::Day => date.into(),
+            DateUnit::Hour => date.and_hms_opt(datetime.hour(), 0, 0).unwrap(),
+            DateUnit::Minute => date.and_hms_opt(datetime.hour(), datetime.minute(), 0).unwrap(),
+            DateUnit::Second => datetime.with_nanosecond(0).unwrap(),
+        }
+    }
+
+    // Moves the datetime by a (possibly negative) number of units. Months and years
+    // keep the day of month, clamped to the length of the target month.
+    fn add(self, datetime: NaiveDateTime, amount: i32) -> Option<NaiveDateTime> {
+        let add_months = |months: i32| {
+            if months >= 0 {
+                datetime.checked_add_months(Months::new(months as u32))
+            } else {
+                datetime.checked_sub_months(Months::new(months.unsigned_abs()))
+            }
+        };
+        let amount64 = amount as i64;
+        match self {
+            DateUnit::Year => add_months(amount.checked_mul(12)?),
+            DateUnit::Quarter => add_months(amount.checked_mul(3)?),
+            DateUnit::Month => add_months(amount),
+            DateUnit::Week => datetime.checked_add_signed(TimeDelta::try_weeks(amount64)?),
+            DateUnit::Day => datetime.checked_add_signed(TimeDelta::try_days(amount64)?),
+            DateUnit::Hour => datetime.checked_add_signed(TimeDelta::try_hours(amount64)?),
+            DateUnit::Minute => datetime.checked_add_signed(TimeDelta::try_minutes(amount64)?),
+            DateUnit::Second => datetime.checked_add_signed(TimeDelta::try_seconds(amount64)?),
+        }
+    }
+}
+
+// Creates a range from start until right before end, Range(0, 0) if either is missing
+fn half_open_range(start: Option<NaiveDateTime>, end: Option<NaiveDateTime>) -> QueryDate {
+    match (start, end) {
+        (Some(start), Some(end)) => QueryDate::Range(local_timestamp(start), local_timestamp(end) - 1),
+        _ => QueryDate::Range(0, 0),
+    }
+}
+
+// Range of amount units before (negative) or after (positive) now
+fn relative_range(now: NaiveDateTime, unit: DateUnit, amount: i32, mode: RelativeDateMode) -> QueryDate {
+    let (current_start, current_end) = match mode {
+        RelativeDateMode::Rolling => (Some(now), Some(now)),
+        RelativeDateMode::Calendar => {
+            let start = unit.start_of(now);
+            (Some(start), unit.add(start, 1))
+        }
+    };
+    if amount < 0 {
+        half_open_range(current_start.and_then(|start| unit.add(start, amount)), current_start)
+    } else {
+        half_open_range(current_end, current_end.and_then(|end| unit.add(end, amount)))
+    }
+}
+
+// Date formats tried in order for absolute dates, alone or followed by a time of day
+fn date_formats(order: DateOrder) -> [&'static str; 4] {
+    match order {
+        DateOrder::MonthFirst => [
+            "%Y-%m-%d", // 2023-12-25
+            "%m/%d/%Y", // 12/25/2023
+            "%d/%m/%Y", // 25/12/2023
+            "%d.%m.%Y", // 25.12.2023
+        ],
+        DateOrder::DayFirst => ["%Y-%m-%d", "%d/%m/%Y", "%m/%d/%Y", "%d.%m.%Y"],
+    }
+}
+
+// Weekday names and abbreviations in English, German and French
+const WEEKDAY_NAMES: &[(&str, Weekday)] = &[
+    ("sunday", Weekday::Sunday),
+    ("sun", Weekday::Sunday),
+    ("monday", Weekday::Monday),
+    ("mon", Weekday::Monday),
+    ("tuesday", Weekday::Tuesday),
+    ("tue", Weekday::Tuesday),
+    ("wednesday", Weekday::Wednesday),
+    ("wed", Weekday::Wednesday),
+    ("thursday", Weekday::Thursday),
+    ("thu", Weekday::Thursday),
+    ("friday", Weekday::Friday),
+    ("fri", Weekday::Friday),
+    ("saturday", Weekday::Saturday),
+    ("sat", Weekday::Saturday),
+    // German
+    ("sonntag", Weekday::Sunday),
+    ("so", Weekday::Sunday),
+    ("montag", Weekday::Monday),
+    ("mo", Weekday::Monday),
+    ("dienstag", Weekday::Tuesday),
+    ("di", Weekday::Tuesday),
+    ("mittwoch", Weekday::Wednesday),
+    ("mi", Weekday::Wednesday),
+    ("donnerstag", Weekday::Thursday),
+    ("do", Weekday::Thursday),
+    ("freitag", Weekday::Friday),
+    ("fr", Weekday::Friday),
+    ("samstag", Weekday::Saturday),
+    ("sonnabend", Weekday::Saturday),
+    ("sa", Weekday::Saturday),
+    // French ("mar" for mardi is left out, it means March)
+    ("dimanche", Weekday::Sunday),
+    ("dim", Weekday::Sunday),
+    ("lundi", Weekday::Monday),
+    ("lun", Weekday::Monday),
+    ("mardi", Weekday::Tuesday),
+    ("mercredi", Weekday::Wednesday),
+    ("mer", Weekday::Wednesday),
+    ("jeudi", Weekday::Thursday),
+    ("jeu", Weekday::Thursday),
+    ("vendredi", Weekday::Friday),
+    ("ven", Weekday::Friday),
+    ("samedi", Weekday::Saturday),
+    ("sam", Weekday::Saturday),
+];
+
+// Month names and abbreviations in English, German and French
+const MONTH_NAMES: &[(&str, Month)] = &[
+    ("january", Month::January),
+    ("jan", Month::January),
+    ("february", Month::February),
+    ("feb", Month::February),
+    ("march", Month::March),
+    ("mar", Month::March),
+    ("april", Month::April),
+    ("apr", Month::April),
+    ("may", Month::May),
+    ("june", Month::June),
+    ("jun", Month::June),
+    ("july", Month::July),
+    ("jul", Month::July),
+    ("august", Month::August),
+    ("aug", Month::August),
+    ("september", Month::September),
+    ("sep", Month::September),
+    ("sept", Month::September),
+    ("october", Month::October),
+    ("oct", Month::October),
+    ("november", Month::November),
+    ("nov", Month::November),
+    ("december", Month::December),
+    ("dec", Month::December),
+    // German
+    ("januar", Month::January),
+    ("februar", Month::February),
+    ("märz", Month::March),
+    ("maerz", Month::March),
+    ("mär", Month::March),
+    ("mai", Month::May),
+    ("juni", Month::June),
+    ("juli", Month::July),
+    ("oktober", Month::October),
+    ("okt", Month::October),
+    ("dezember", Month::December),
+    ("dez", Month::December),
+    // French
+    ("janvier", Month::January),
+    ("janv", Month::January),
+    ("février", Month::February),
+    ("fevrier", Month::February),
+    ("févr", Month::February),
+    ("fevr", Month::February),
+    ("mars", Month::March),
+    ("avril", Month::April),
+    ("avr", Month::April),
+    ("juin", Month::June),
+    ("juillet", Month::July),
+    ("juil", Month::July),
+    ("août", Month::August),
+    ("aout", Month::August),
+    ("septembre", Month::September),
+    ("octobre", Month::October),
+    ("novembre", Month::November),
+    ("décembre", Month::December),
+    ("decembre", Month::December),
+    ("déc", Month::December),
+];
+
+// Converts a local date and time to a unix timestamp, 0 if it does not exist (DST gap)
+fn local_timestamp(datetime: NaiveDateTime) -> i64 {
+    Local
+        .from_local_datetime(&datetime)
+        .earliest()
+        .map(|dt| dt.timestamp())
+        .unwrap_or(0)
+}
+
+// Parses a time of day like "14", "14:30" or "14:30:15" and returns it together with
+// its precision in seconds, so that "14:30" covers the whole minute
+fn parse_time_of_day(s: &str) -> Option<(NaiveTime, i64)> {
+    if let Ok(time) = NaiveTime::parse_from_str(s, "%H:%M:%S%.f") {
+        return Some((time, 1));
+    }
+    if let Ok(time) = NaiveTime::parse_from_str(s, "%H:%M") {
+        return Some((time, 60));
+    }
+    if s.len() <= 2
+        && let Ok(hour) = s.parse::<u32>()
+    {
+        return Some((NaiveTime::from_hms_opt(hour, 0, 0)?, 3600));
+    }
+    None
+}
+
+// Parses a date with a time of day, separated by a space or ISO 8601 "t",
+// e.g. "2024-03-01 14:30" or "2024-03-01t14:30:00"
+fn parse_datetime(s: &str, order: DateOrder) -> Option<QueryDate> {
+    // Full RFC 3339 timestamps carry their own UTC offset
+    if let Ok(datetime) = chrono::DateTime::parse_from_rfc3339(s) {
+        return Some(QueryDate::Range(datetime.timestamp(), datetime.timestamp()));
+    }
+    let (date_part, time_part) = s.split_once([' ', 't'])?;
+    let date = date_formats(order)
+        .iter()
+        .find_map(|format| NaiveDate::parse_from_str(date_part, format).ok())?;
+    let (time, precision) = parse_time_of_day(time_part)?;
+    let start = local_timestamp(date.and_time(time));
+    Some(QueryDate::Range(start, start + precision - 1))
+}
+
 impl From<&str> for QueryDate {
     fn from(s: &str) -> Self {
-        use chrono::{Datelike, Local, NaiveDate, NaiveTime, TimeZone};
-        
+        QueryDate::parse(s, &DateOptions::default())
+    }
+}
+
+impl QueryDate {
+    /// Parses a date relative to the current local time
+    pub fn parse(s: &str, options: &DateOptions) -> Self {
+        QueryDate::parse_at(s, options, Local::now().naive_local())
+    }
+
+    /// Parses a date, resolving relative dates like "today" or "last2weeks" against `now`
+    pub fn parse_at(s: &str, options: &DateOptions, now: NaiveDateTime) -> Self {
         let s = s.to_lowercase();
         
         // Handle "unknown" keyword
         if s == "unknown" {
             return QueryDate::Unknown;
         }
+
+        // Handle ranges like "2024-01-01..2024-03-31" or "today..14:00",
+        // spanning from the start of the first to the end of the second date
+        if let Some((from, to)) = s.split_once("..") {
+            return match (
+                QueryDate::parse_at(from, options, now),
+                QueryDate::parse_at(to, options, now),
+            ) {
+                (QueryDate::Range(start, _), QueryDate::Range(_, end)) if start != 0 && end != 0 => {
+                    QueryDate::Range(start, end)
+                }
+                _ => QueryDate::Range(0, 0),
+            };
+        }
         
         // Handle weekdays
-        match s.as_str() {
-            "sunday" | "sun" => return QueryDate::Weekday(Weekday::Sunday),
-            "monday" | "mon" => return QueryDate::Weekday(Weekday::Monday),
-            "tuesday" | "tue" => return QueryDate::Weekday(Weekday::Tuesday),
-            "wednesday" | "wed" => return QueryDate::Weekday(Weekday::Wednesday),
-            "thursday" | "thu" => return QueryDate::Weekday(Weekday::Thursday),
-            "friday" | "fri" => return QueryDate::Weekday(Weekday::Friday),
-            "saturday" | "sat" => return QueryDate::Weekday(Weekday::Saturday),
-            _ => {}
+        if let Some(&(_, weekday)) = WEEKDAY_NAMES.iter().find(|(name, _)| *name == s) {
+            return QueryDate::Weekday(weekday);
         }
-        
+
         // Handle months
-        match s.as_str() {
-            "january" | "jan" => return QueryDate::Month(Month::January),
-            "february" | "feb" => return QueryDate::Month(Month::February),
-            "march" | "mar" => return QueryDate::Month(Month::March),
-            "april" | "apr" => return QueryDate::Month(Month::April),
-            "may" => return QueryDate::Month(Month::May),
-            "june" | "jun" => return QueryDate::Month(Month::June),
-            "july" | "jul" => return QueryDate::Month(Month::July),
-            "august" | "aug" => return QueryDate::Month(Month::August),
-            "september" | "sep" => return QueryDate::Month(Month::September),
-            "october" | "oct" => return QueryDate::Month(Month::October),
-            "november" | "nov" => return QueryDate::Month(Month::November),
-            "december" | "dec" => return QueryDate::Month(Month::December),
-            _ => {}
+        if let Some(&(_, month)) = MONTH_NAMES.iter().find(|(name, _)| *name == s) {
+            return QueryDate::Month(month);
         }
-        
+
         // Helper function to create timestamp range from start and end dates
         let date_range_to_timestamps = |start_date: NaiveDate, end_date: NaiveDate| -> (i64, i64) {
             let start_datetime = start_date.and_time(NaiveTime::from_hms_opt(0, 0, 0).unwrap());
             let end_datetime = end_date.and_time(NaiveTime::from_hms_opt(23, 59, 59).unwrap());
-            
-            let start_timestamp = Local.from_local_datetime(&start_datetime)
-                .single()
-                .map(|dt| dt.timestamp())
-                .unwrap_or(0);
-            let end_timestamp = Local.from_local_datetime(&end_datetime)
-                .single()
-                .map(|dt| dt.timestamp())
-                .unwrap_or(0);
-                
-            (start_timestamp, end_timestamp)
+
+            (local_timestamp(start_datetime), local_timestamp(end_datetime))
         };
         
         // Handle special date constants
-        let today = Local::now().date_naive();
+        let today = now.date();
         match s.as_str() {
             "today" => {
                 let (start, end) = date_range_to_timestamps(today, today);
@@ -68,203 +383,104 @@ impl From<&str> for QueryDate {
                 let (start, end) = date_range_to_timestamps(yesterday, yesterday);
                 return QueryDate::Range(start, end);
             },
-            // Week-based constants
-            "lastweek" | "last week" | "pastweek" | "past week" | "prevweek" | "prev week" => {
-                let days_since_monday = today.weekday().num_days_from_monday();
-                let last_monday = today - chrono::Duration::days((days_since_monday + 7) as i64);
-                let last_sunday = last_monday + chrono::Duration::days(6);
-                let (start, end) = date_range_to_timestamps(last_monday, last_sunday);
-                return QueryDate::Range(start, end);
-            },
-            "thisweek" | "this week" | "currentweek" | "current week" => {
-                let days_since_monday = today.weekday().num_days_from_monday();
-                let this_monday = today - chrono::Duration::days(days_since_monday as i64);
-                let this_sunday = this_monday + chrono::Duration::days(6);
-                let (start, end) = date_range_to_timestamps(this_monday, this_sunday);
-                return QueryDate::Range(start, end);
-            },
-            "nextweek" | "next week" | "comingweek" | "coming week" => {
-                let days_since_monday = today.weekday().num_days_from_monday();
-                let next_monday = today + chrono::Duration::days((7 - days_since_monday) as i64);
-                let next_sunday = next_monday + chrono::Duration::days(6);
-                let (start, end) = date_range_to_timestamps(next_monday, next_sunday);
-                return QueryDate::Range(start, end);
-            },
-            // Month-based constants
-            "lastmonth" | "last month" | "pastmonth" | "past month" | "prevmonth" | "prev month" => {
-                let last_month = if today.month() == 1 {
-                    NaiveDate::from_ymd_opt(today.year() - 1, 12, 1).unwrap()
-                } else {
-                    NaiveDate::from_ymd_opt(today.year(), today.month() - 1, 1).unwrap()
-                };
-                let last_month_end = last_month + chrono::Duration::days(
-                    chrono::NaiveDate::from_ymd_opt(
-                        if last_month.month() == 12 { last_month.year() + 1 } else { last_month.year() },
-                        if last_month.month() == 12 { 1 } else { last_month.month() + 1 },
-                        1
-                    ).unwrap().signed_duration_since(last_month).num_days() - 1
-                );
-                let (start, end) = date_range_to_timestamps(last_month, last_month_end);
-                return QueryDate::Range(start, end);
-            },
-            "thismonth" | "this month" | "currentmonth" | "current month" => {
-                let this_month_start = NaiveDate::from_ymd_opt(today.year(), today.month(), 1).unwrap();
-                let next_month = if today.month() == 12 {
-                    NaiveDate::from_ymd_opt(today.year() + 1, 1, 1).unwrap()
-                } else {
-                    NaiveDate::from_ymd_opt(today.year(), today.month() + 1, 1).unwrap()
-                };
-                let this_month_end = next_month - chrono::Duration::days(1);
-                let (start, end) = date_range_to_timestamps(this_month_start, this_month_end);
-                return QueryDate::Range(start, end);
-            },
-            "nextmonth" | "next month" | "comingmonth" | "coming month" => {
-                let next_month_start = if today.month() == 12 {
-                    NaiveDate::from_ymd_opt(today.year() + 1, 1, 1).unwrap()
-                } else {
-                    NaiveDate::from_ymd_opt(today.year(), today.month() + 1, 1).unwrap()
-                };
-                let month_after_next = if next_month_start.month() == 12 {
-                    NaiveDate::from_ymd_opt(next_month_start.year() + 1, 1, 1).unwrap()
-                } else {
-                    NaiveDate::from_ymd_opt(next_month_start.year(), next_month_start.month() + 1, 1).unwrap()
-                };
-                let next_month_end = month_after_next - chrono::Duration::days(1);
-                let (start, end) = date_range_to_timestamps(next_month_start, next_month_end);
-                return QueryDate::Range(start, end);
-            },
-            // Year-based constants
-            "lastyear" | "last year" | "pastyear" | "past year" | "prevyear" | "prev year" => {
-                let last_year_start = NaiveDate::from_ymd_opt(today.year() - 1, 1, 1).unwrap();
-                let last_year_end = NaiveDate::from_ymd_opt(today.year() - 1, 12, 31).unwrap();
-                let (start, end) = date_range_to_timestamps(last_year_start, last_year_end);
-                return QueryDate::Range(start, end);
-            },
-            "thisyear" | "this year" | "currentyear" | "current year" => {
-                let this_year_start = NaiveDate::from_ymd_opt(today.year(), 1, 1).unwrap();
-                let this_year_end = NaiveDate::from_ymd_opt(today.year(), 12, 31).unwrap();
-                let (start, end) = date_range_to_timestamps(this_year_start, this_year_end);
-                return QueryDate::Range(start, end);
-            },
-            "nextyear" | "next year" | "comingyear" | "coming year" => {
-                let next_year_start = NaiveDate::from_ymd_opt(today.year() + 1, 1, 1).unwrap();
-                let next_year_end = NaiveDate::from_ymd_opt(today.year() + 1, 12, 31).unwrap();
-                let (start, end) = date_range_to_timestamps(next_year_start, next_year_end);
-                return QueryDate::Range(start, end);
-            },
             _ => {}
         }
-        
-        // Handle numeric relative dates like "last3days", "next2weeks", etc.
-        if let Some(captures) = regex::Regex::new(r"^(last|past|prev|next|coming)(\d+)(years?|months?|weeks?|days?|hours?|minutes?|mins?|seconds?|secs?)$").unwrap().captures(&s) {
+
+        // Handle the current calendar unit like "thisweek" or "current month"
+        if let Some(captures) = CURRENT_UNIT_REGEX.captures(&s)
+            && let Some(unit) = DateUnit::parse(&captures[2])
+        {
+            let start = unit.start_of(now);
+            return half_open_range(Some(start), unit.add(start, 1));
+        }
+
+        // Handle relative dates like "lastweek", "last3days", "past2months" or "next2weeks"
+        if let Some(captures) = RELATIVE_REGEX.captures(&s)
+            && let Some(unit) = DateUnit::parse(&captures[3])
+        {
             let direction = &captures[1];
-            let amount = captures[2].parse::<i64>().unwrap_or(1);
-            let unit = &captures[3];
-            
-            let is_backwards = matches!(direction, "last" | "past" | "prev");
-            let duration_amount = if is_backwards { -amount } else { amount };
-            
-            let (start_date, end_date) = match unit {
-                "year" | "years" => {
-                    let target_year = today.year() + duration_amount as i32;
-                    let year_start = NaiveDate::from_ymd_opt(target_year, 1, 1).unwrap_or(today);
-                    let year_end = NaiveDate::from_ymd_opt(target_year, 12, 31).unwrap_or(today);
-                    if is_backwards {
-                        // For "last X years", include from X years ago until today
-                        let start = NaiveDate::from_ymd_opt(today.year() + duration_amount as i32, 1, 1).unwrap_or(today);
-                        (start, today)
-                    } else {
-                        (year_start, year_end)
-                    }
-                },
-                "month" | "months" => {
-                    if is_backwards {
-                        // For "last X months", go back X months from today
-                        let months_ago = today - chrono::Duration::days(amount * 30); // Approximation
-                        (months_ago, today)
-                    } else {
-                        // For "next X months", go forward X months from today
-                        let months_ahead = today + chrono::Duration::days(amount * 30); // Approximation
-                        (today, months_ahead)
-                    }
-                },
-                "week" | "weeks" => {
-                    if is_backwards {
-                        let weeks_ago = today - chrono::Duration::weeks(amount);
-                        (weeks_ago, today)
-                    } else {
-                        let weeks_ahead = today + chrono::Duration::weeks(amount);
-                        (today, weeks_ahead)
-                    }
-                },
-                "day" | "days" => {
-                    if is_backwards {
-                        let days_ago = today - chrono::Duration::days(amount);
-                        (days_ago, today)
-                    } else {
-                        let days_ahead = today + chrono::Duration::days(amount);
-                        (today, days_ahead)
-                    }
-                },
-                "hour" | "hours" => {
-                    // For hours, minutes, seconds - use current time as base
-                    let now = Local::now();
-                    if is_backwards {
-                        let hours_ago = now - chrono::Duration::hours(amount);
-                        return QueryDate::Range(hours_ago.timestamp(), now.timestamp());
-                    } else {
-                        let hours_ahead = now + chrono::Duration::hours(amount);
-                        return QueryDate::Range(now.timestamp(), hours_ahead.timestamp());
-                    }
-                },
-                "minute" | "minutes" | "min" | "mins" => {
-                    let now = Local::now();
-                    if is_backwards {
-                        let minutes_ago = now - chrono::Duration::minutes(amount);
-                        return QueryDate::Range(minutes_ago.timestamp(), now.timestamp());
-                    } else {
-                        let minutes_ahead = now + chrono::Duration::minutes(amount);
-                        return QueryDate::Range(now.timestamp(), minutes_ahead.timestamp());
-                    }
-                },
-                "second" | "seconds" | "sec" | "secs" => {
-                    let now = Local::now();
-                    if is_backwards {
-                        let seconds_ago = now - chrono::Duration::seconds(amount);
-                        return QueryDate::Range(seconds_ago.timestamp(), now.timestamp());
-                    } else {
-                        let seconds_ahead = now + chrono::Duration::seconds(amount);
-                        return QueryDate::Range(now.timestamp(), seconds_ahead.timestamp());
-                    }
-                },
-                _ => (today, today), // Fallback
+            let amount = captures[2].parse::<i32>().unwrap_or(1);
+            let mode = match direction {
+                "past" | "coming" => options.past_mode,
+                _ => options.last_mode,
             };
-            
-            let (start, end) = date_range_to_timestamps(start_date, end_date);
-            return QueryDate::Range(start, end);
+            let amount = if matches!(direction, "last" | "past" | "prev") {
+                -amount
+            } else {
+                amount
+            };
+            return relative_range(now, unit, amount, mode);
+        }
+
+        // Handle points in time like "2weeksago" or "3 days ago"
+        if let Some(captures) = AGO_REGEX.captures(&s)
+            && let Some(unit) = DateUnit::parse(&captures[2])
+            && let Ok(amount) = captures[1].parse::<i32>()
+        {
+            // Whole day for date units, whole hour/minute/second for time units
+            let precision = unit.max(DateUnit::Day);
+            let start = unit
+                .add(now, -amount)
+                .map(|point| precision.start_of(point));
+            return half_open_range(start, start.and_then(|start| precision.add(start, 1)));
         }
         
-        // Try to parse as year only (4 digits)
-        if let Ok(year) = s.parse::<i32>() {
-            if year >= 1970 && year <= 9999 {
-                if let Some(start_date) = NaiveDate::from_ymd_opt(year, 1, 1) {
-                    if let Some(end_date) = NaiveDate::from_ymd_opt(year, 12, 31) {
-                        let (start, end) = date_range_to_timestamps(start_date, end_date);
-                        return QueryDate::Range(start, end);
-                    }
+        // Handle quarters like "2024-q1", or "q1" for the current year
+        if let Some(captures) = QUARTER_REGEX.captures(&s) {
+            let year = captures
+                .get(1)
+                .map_or(Some(today.year()), |year| year.as_str().parse().ok());
+            let quarter = captures[2].parse::<u32>().unwrap_or(1);
+            let start = year.and_then(|year| NaiveDate::from_ymd_opt(year, (quarter - 1) * 3 + 1, 1));
+            let start = start.map(NaiveDateTime::from);
+            return half_open_range(start, start.and_then(|start| DateUnit::Quarter.add(start, 1)));
+        }
+
+        // Handle ISO weeks like "2024-w12", or a single day of it like "2024-w12-3"
+        if let Some(captures) = ISO_WEEK_REGEX.captures(&s) {
+            let year = captures[1].parse::<i32>().unwrap_or(0);
+            let week = captures[2].parse::<u32>().unwrap_or(0);
+            let day = captures.get(3).and_then(|day| day.as_str().parse::<u32>().ok());
+            let monday = NaiveDate::from_isoywd_opt(year, week, chrono::Weekday::Mon);
+            return match (monday, day) {
+                (Some(monday), Some(day)) => {
+                    let date = monday + TimeDelta::days(day as i64 - 1);
+                    let (start, end) = date_range_to_timestamps(date, date);
+                    QueryDate::Range(start, end)
                 }
-            }
+                (Some(monday), None) => {
+                    let (start, end) = date_range_to_timestamps(monday, monday + TimeDelta::days(6));
+                    QueryDate::Range(start, end)
+                }
+                _ => QueryDate::Range(0, 0),
+            };
         }
-        
+
+        // Try to parse as year only (4 digits)
+        if let Ok(year) = s.parse::<i32>()
+            && (1970..=9999).contains(&year)
+            && let Some(start_date) = NaiveDate::from_ymd_opt(year, 1, 1)
+            && let Some(end_date) = NaiveDate::from_ymd_opt(year, 12, 31)
+        {
+            let (start, end) = date_range_to_timestamps(start_date, end_date);
+            return QueryDate::Range(start, end);
+        }
+
+        // Try to parse as date with time of day, e.g. "2024-03-01 14:30" or ISO "2024-03-01t14:30"
+        if let Some(date) = parse_datetime(&s, options.date_order) {
+            return date;
+        }
+
+        // Try to parse as time of day only, e.g. "14:00" for today at 14:00
+        if s.contains(':')
+            && let Some((time, precision)) = parse_time_of_day(&s)
+        {
+            let start = local_timestamp(today.and_time(time));
+            return QueryDate::Range(start, start + precision - 1);
+        }
+
         // Try various date formats using chrono's parsing
-        let date_formats = [
-            "%Y-%m-%d",        // 2023-12-25
-            "%Y-%m-%d %H:%M:%S", // 2023-12-25 12:30:45 (will use date part only)
-            "%m/%d/%Y",        // 12/25/2023
-            "%d/%m/%Y",        // 25/12/2023
-        ];
-        
-        for format in &date_formats {
+        for format in &date_formats(options.date_order) {
             if let Ok(parsed_date) = NaiveDate::parse_from_str(&s, format) {
                 let (start, end) = date_range_to_timestamps(parsed_date, parsed_date);
                 return QueryDate::Range(start, end);
@@ -272,29 +488,29 @@ impl From<&str> for QueryDate {
         }
         
         // Try to parse MM/YYYY or YYYY/MM format
-        if let Some(captures) = regex::Regex::new(r"^(\d{1,4})/(\d{1,4})$").unwrap().captures(&s) {
-            if let (Ok(first), Ok(second)) = (captures[1].parse::<u32>(), captures[2].parse::<u32>()) {
-                let (year, month) = if first >= 1970 && first <= 9999 && second >= 1 && second <= 12 {
-                    (first as i32, second)
-                } else if second >= 1970 && second <= 9999 && first >= 1 && first <= 12 {
-                    (second as i32, first)
-                } else {
-                    return QueryDate::Range(0, 0);
+        if let Some(captures) = regex::Regex::new(r"^(\d{1,4})/(\d{1,4})$").unwrap().captures(&s)
+            && let (Ok(first), Ok(second)) = (captures[1].parse::<u32>(), captures[2].parse::<u32>())
+        {
+            let (year, month) = if (1970..=9999).contains(&first) && (1..=12).contains(&second) {
+                (first as i32, second)
+            } else if (1970..=9999).contains(&second) && (1..=12).contains(&first) {
+                (second as i32, first)
+            } else {
+                return QueryDate::Range(0, 0);
+            };
+            
+            if let Some(start_date) = NaiveDate::from_ymd_opt(year, month, 1) {
+                // Get last day of month
+                let next_month = if month == 12 { 
+                    NaiveDate::from_ymd_opt(year + 1, 1, 1)
+                } else { 
+                    NaiveDate::from_ymd_opt(year, month + 1, 1)
                 };
                 
-                if let Some(start_date) = NaiveDate::from_ymd_opt(year, month, 1) {
-                    // Get last day of month
-                    let next_month = if month == 12 { 
-                        NaiveDate::from_ymd_opt(year + 1, 1, 1)
-                    } else { 
-                        NaiveDate::from_ymd_opt(year, month + 1, 1)
-                    };
-                    
-                    if let Some(next_month_date) = next_month {
-                        let end_date = next_month_date - chrono::Duration::days(1);
-                        let (start, end) = date_range_to_timestamps(start_date, end_date);
-                        return QueryDate::Range(start, end);
-                    }
+                if let Some(next_month_date) = next_month {
+                    let end_date = next_month_date - chrono::Duration::days(1);
+                    let (start, end) = date_range_to_timestamps(start_date, end_date);
+                    return QueryDate::Range(start, end);
                 }
             }
         }
@@ -913,4 +1129,348 @@ mod tests {
         assert_eq!(QueryDate::from("2023-13-45"), QueryDate::Range(0, 0)); // Invalid date
         assert_eq!(QueryDate::from(""), QueryDate::Range(0, 0)); // Empty string
     }
+
+    #[test]
+    fn test_datetime_parsing() {
+        use chrono::{Local, TimeZone};
+        let expected_start = Local
+            .with_ymd_and_hms(2024, 3, 1, 14, 30, 0)
+            .unwrap()
+            .timestamp();
+
+        // Minute precision covers the whole minute
+        assert_eq!(
+            QueryDate::from("2024-03-01 14:30"),
+            QueryDate::Range(expected_start, expected_start + 59)
+        );
+        // ISO 8601 "T" separator
+        assert_eq!(
+            QueryDate::from("2024-03-01T14:30"),
+            QueryDate::Range(expected_start, expected_start + 59)
+        );
+        // Second precision is a single instant
+        assert_eq!(
+            QueryDate::from("2024-03-01 14:30:00"),
+            QueryDate::Range(expected_start, expected_start)
+        );
+        assert_eq!(
+            QueryDate::from("2024-03-01T14:30:00"),
+            QueryDate::Range(expected_start, expected_start)
+        );
+        // Hour precision covers the whole hour
+        assert_eq!(
+            QueryDate::from("2024-03-01 14"),
+            QueryDate::Range(expected_start - 30 * 60, expected_start + 30 * 60 - 1)
+        );
+        // Times work with the other date formats as well
+        assert_eq!(
+            QueryDate::from("03/01/2024 14:30"),
+            QueryDate::Range(expected_start, expected_start + 59)
+        );
+        // RFC 3339 with explicit offset
+        assert_eq!(
+            QueryDate::from("2024-03-01T14:30:00Z"),
+            QueryDate::Range(1709303400, 1709303400)
+        );
+
+        // Invalid times
+        assert_eq!(QueryDate::from("2024-03-01 25:00"), QueryDate::Range(0, 0));
+        assert_eq!(QueryDate::from("2024-03-01 14:61"), QueryDate::Range(0, 0));
+        assert_eq!(QueryDate::from("2024-03-01 abc"), QueryDate::Range(0, 0));
+    }
+
+    #[test]
+    fn test_time_only_parsing() {
+        use chrono::{Local, TimeZone};
+        let today = Local::now().date_naive();
+        let expected_start = Local
+            .from_local_datetime(&today.and_hms_opt(14, 0, 0).unwrap())
+            .earliest()
+            .unwrap()
+            .timestamp();
+        assert_eq!(
+            QueryDate::from("14:00"),
+            QueryDate::Range(expected_start, expected_start + 59)
+        );
+        assert_eq!(
+            QueryDate::from("14:00:00"),
+            QueryDate::Range(expected_start, expected_start)
+        );
+        assert_eq!(QueryDate::from("24:00"), QueryDate::Range(0, 0));
+    }
+
+    #[test]
+    fn test_date_ranges() {
+        // Range from the start of the first to the end of the second date
+        let QueryDate::Range(first_start, _) = QueryDate::from("2024-01-01") else {
+            panic!("Expected Range for '2024-01-01'");
+        };
+        let QueryDate::Range(_, second_end) = QueryDate::from("2024-03-31") else {
+            panic!("Expected Range for '2024-03-31'");
+        };
+        assert_eq!(
+            QueryDate::from("2024-01-01..2024-03-31"),
+            QueryDate::Range(first_start, second_end)
+        );
+
+        // Today until a time of day
+        let QueryDate::Range(today_start, _) = QueryDate::from("today") else {
+            panic!("Expected Range for 'today'");
+        };
+        let QueryDate::Range(_, time_end) = QueryDate::from("14:00") else {
+            panic!("Expected Range for '14:00'");
+        };
+        assert_eq!(
+            QueryDate::from("today..14:00"),
+            QueryDate::Range(today_start, time_end)
+        );
+
+        // Invalid bounds
+        assert_eq!(QueryDate::from("2024-01-01..abc"), QueryDate::Range(0, 0));
+        assert_eq!(QueryDate::from("..2024"), QueryDate::Range(0, 0));
+        assert_eq!(QueryDate::from("monday..friday"), QueryDate::Range(0, 0));
+    }
+
+    fn local_ts(year: i32, month: u32, day: u32, hour: u32, min: u32, sec: u32) -> i64 {
+        local_timestamp(
+            NaiveDate::from_ymd_opt(year, month, day)
+                .unwrap()
+                .and_hms_opt(hour, min, sec)
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_calendar_relative_dates() {
+        let options = DateOptions::default();
+        // Wednesday
+        let now = NaiveDate::from_ymd_opt(2024, 5, 15)
+            .unwrap()
+            .and_hms_opt(14, 20, 30)
+            .unwrap();
+        let parse = |s: &str| QueryDate::parse_at(s, &options, now);
+
+        // "last" covers whole calendar units before the current one
+        assert_eq!(
+            parse("last2months"),
+            QueryDate::Range(local_ts(2024, 3, 1, 0, 0, 0), local_ts(2024, 4, 30, 23, 59, 59))
+        );
+        assert_eq!(
+            parse("last2years"),
+            QueryDate::Range(local_ts(2022, 1, 1, 0, 0, 0), local_ts(2023, 12, 31, 23, 59, 59))
+        );
+        assert_eq!(
+            parse("lastweek"),
+            QueryDate::Range(local_ts(2024, 5, 6, 0, 0, 0), local_ts(2024, 5, 12, 23, 59, 59))
+        );
+        assert_eq!(
+            parse("last 3 days"),
+            QueryDate::Range(local_ts(2024, 5, 12, 0, 0, 0), local_ts(2024, 5, 14, 23, 59, 59))
+        );
+        assert_eq!(
+            parse("last2hours"),
+            QueryDate::Range(local_ts(2024, 5, 15, 12, 0, 0), local_ts(2024, 5, 15, 13, 59, 59))
+        );
+        assert_eq!(
+            parse("next3months"),
+            QueryDate::Range(local_ts(2024, 6, 1, 0, 0, 0), local_ts(2024, 8, 31, 23, 59, 59))
+        );
+
+        // "this" covers the current unit
+        assert_eq!(
+            parse("thismonth"),
+            QueryDate::Range(local_ts(2024, 5, 1, 0, 0, 0), local_ts(2024, 5, 31, 23, 59, 59))
+        );
+        assert_eq!(
+            parse("this week"),
+            QueryDate::Range(local_ts(2024, 5, 13, 0, 0, 0), local_ts(2024, 5, 19, 23, 59, 59))
+        );
+    }
+
+    #[test]
+    fn test_rolling_relative_dates() {
+        let options = DateOptions::default();
+        let now = NaiveDate::from_ymd_opt(2024, 5, 15)
+            .unwrap()
+            .and_hms_opt(14, 20, 30)
+            .unwrap();
+        let parse = |s: &str| QueryDate::parse_at(s, &options, now);
+        let now_ts = local_ts(2024, 5, 15, 14, 20, 30);
+
+        // "past" is a window ending now
+        assert_eq!(
+            parse("past2months"),
+            QueryDate::Range(local_ts(2024, 3, 15, 14, 20, 30), now_ts - 1)
+        );
+        assert_eq!(
+            parse("past2years"),
+            QueryDate::Range(local_ts(2022, 5, 15, 14, 20, 30), now_ts - 1)
+        );
+        assert_eq!(
+            parse("coming1week"),
+            QueryDate::Range(now_ts, local_ts(2024, 5, 22, 14, 20, 30) - 1)
+        );
+
+        // Days of month are clamped to the target month
+        let end_of_month = NaiveDate::from_ymd_opt(2024, 3, 31)
+            .unwrap()
+            .and_hms_opt(12, 0, 0)
+            .unwrap();
+        assert_eq!(
+            QueryDate::parse_at("past1month", &options, end_of_month),
+            QueryDate::Range(local_ts(2024, 2, 29, 12, 0, 0), local_ts(2024, 3, 31, 12, 0, 0) - 1)
+        );
+
+        // The mode is configurable
+        let rolling_last = DateOptions {
+            last_mode: RelativeDateMode::Rolling,
+            ..DateOptions::default()
+        };
+        assert_eq!(
+            QueryDate::parse_at("last2months", &rolling_last, now),
+            parse("past2months")
+        );
+        let calendar_past = DateOptions {
+            past_mode: RelativeDateMode::Calendar,
+            ..DateOptions::default()
+        };
+        assert_eq!(
+            QueryDate::parse_at("past2months", &calendar_past, now),
+            parse("last2months")
+        );
+    }
+
+    #[test]
+    fn test_ago_dates() {
+        let options = DateOptions::default();
+        let now = NaiveDate::from_ymd_opt(2024, 5, 15)
+            .unwrap()
+            .and_hms_opt(14, 20, 30)
+            .unwrap();
+        let parse = |s: &str| QueryDate::parse_at(s, &options, now);
+
+        // Date units cover the whole day
+        assert_eq!(
+            parse("2weeksago"),
+            QueryDate::Range(local_ts(2024, 5, 1, 0, 0, 0), local_ts(2024, 5, 1, 23, 59, 59))
+        );
+        assert_eq!(
+            parse("1 month ago"),
+            QueryDate::Range(local_ts(2024, 4, 15, 0, 0, 0), local_ts(2024, 4, 15, 23, 59, 59))
+        );
+        // Time units cover the whole unit
+        assert_eq!(
+            parse("3hoursago"),
+            QueryDate::Range(local_ts(2024, 5, 15, 11, 0, 0), local_ts(2024, 5, 15, 11, 59, 59))
+        );
+        assert_eq!(
+            parse("10secsago"),
+            QueryDate::Range(local_ts(2024, 5, 15, 14, 20, 20), local_ts(2024, 5, 15, 14, 20, 20))
+        );
+        assert_eq!(parse("weeksago"), QueryDate::Range(0, 0));
+    }
+
+    #[test]
+    fn test_localized_names() {
+        // German
+        assert_eq!(QueryDate::from("Montag"), QueryDate::Weekday(Weekday::Monday));
+        assert_eq!(QueryDate::from("do"), QueryDate::Weekday(Weekday::Thursday));
+        assert_eq!(QueryDate::from("März"), QueryDate::Month(Month::March));
+        assert_eq!(QueryDate::from("dezember"), QueryDate::Month(Month::December));
+        // French
+        assert_eq!(QueryDate::from("mercredi"), QueryDate::Weekday(Weekday::Wednesday));
+        assert_eq!(QueryDate::from("samedi"), QueryDate::Weekday(Weekday::Saturday));
+        assert_eq!(QueryDate::from("Février"), QueryDate::Month(Month::February));
+        assert_eq!(QueryDate::from("août"), QueryDate::Month(Month::August));
+        // "mar" stays March
+        assert_eq!(QueryDate::from("mar"), QueryDate::Month(Month::March));
+    }
+
+    #[test]
+    fn test_date_order() {
+        let month_first = DateOptions::default();
+        let day_first = DateOptions {
+            date_order: DateOrder::DayFirst,
+            ..DateOptions::default()
+        };
+        let now = NaiveDate::from_ymd_opt(2024, 5, 15)
+            .unwrap()
+            .and_hms_opt(14, 20, 30)
+            .unwrap();
+
+        assert_eq!(
+            QueryDate::parse_at("03/04/2024", &month_first, now),
+            QueryDate::Range(local_ts(2024, 3, 4, 0, 0, 0), local_ts(2024, 3, 4, 23, 59, 59))
+        );
+        assert_eq!(
+            QueryDate::parse_at("03/04/2024", &day_first, now),
+            QueryDate::Range(local_ts(2024, 4, 3, 0, 0, 0), local_ts(2024, 4, 3, 23, 59, 59))
+        );
+        assert_eq!(
+            QueryDate::parse_at("03/04/2024 10:15", &day_first, now),
+            QueryDate::Range(local_ts(2024, 4, 3, 10, 15, 0), local_ts(2024, 4, 3, 10, 15, 59))
+        );
+        // Unambiguous dates parse with either preference
+        assert_eq!(
+            QueryDate::parse_at("25/12/2023", &month_first, now),
+            QueryDate::parse_at("12/25/2023", &day_first, now)
+        );
+        // Dotted dates are always day first
+        assert_eq!(
+            QueryDate::parse_at("03.04.2024", &month_first, now),
+            QueryDate::Range(local_ts(2024, 4, 3, 0, 0, 0), local_ts(2024, 4, 3, 23, 59, 59))
+        );
+    }
+
+    #[test]
+    fn test_quarters() {
+        let options = DateOptions::default();
+        let now = NaiveDate::from_ymd_opt(2024, 5, 15)
+            .unwrap()
+            .and_hms_opt(14, 20, 30)
+            .unwrap();
+        let parse = |s: &str| QueryDate::parse_at(s, &options, now);
+
+        assert_eq!(
+            parse("2024-Q1"),
+            QueryDate::Range(local_ts(2024, 1, 1, 0, 0, 0), local_ts(2024, 3, 31, 23, 59, 59))
+        );
+        assert_eq!(
+            parse("2023q4"),
+            QueryDate::Range(local_ts(2023, 10, 1, 0, 0, 0), local_ts(2023, 12, 31, 23, 59, 59))
+        );
+        assert_eq!(parse("q2"), parse("2024-q2"));
+        assert_eq!(
+            parse("thisquarter"),
+            QueryDate::Range(local_ts(2024, 4, 1, 0, 0, 0), local_ts(2024, 6, 30, 23, 59, 59))
+        );
+        assert_eq!(parse("lastquarter"), parse("2024-q1"));
+        assert_eq!(
+            parse("last2quarters"),
+            QueryDate::Range(local_ts(2023, 10, 1, 0, 0, 0), local_ts(2024, 3, 31, 23, 59, 59))
+        );
+        assert_eq!(parse("2024-q5"), QueryDate::Range(0, 0));
+    }
+
+    #[test]
+    fn test_iso_weeks() {
+        // 2024-W12 is Monday March 18th to Sunday March 24th
+        assert_eq!(
+            QueryDate::from("2024-W12"),
+            QueryDate::Range(local_ts(2024, 3, 18, 0, 0, 0), local_ts(2024, 3, 24, 23, 59, 59))
+        );
+        assert_eq!(QueryDate::from("2024w12"), QueryDate::from("2024-W12"));
+        // Single day of the week
+        assert_eq!(
+            QueryDate::from("2024-W12-3"),
+            QueryDate::Range(local_ts(2024, 3, 20, 0, 0, 0), local_ts(2024, 3, 20, 23, 59, 59))
+        );
+        // Week 1 may start in the previous year
+        assert_eq!(
+            QueryDate::from("2025-W01"),
+            QueryDate::Range(local_ts(2024, 12, 30, 0, 0, 0), local_ts(2025, 1, 5, 23, 59, 59))
+        );
+        // 2024 has no week 53
+        assert_eq!(QueryDate::from("2024-W53"), QueryDate::Range(0, 0));
+    }
 }
@@ -0,0 +1,108 @@
+// Number of typos allowed in a word of the given length
+pub fn max_distance(word_len: usize) -> usize {
+    match word_len {
+        0..=3 => 0,
+        4..=6 => 1,
+        _ => 2,
+    }
+}
+
+// Edit distance of the best matching substring of text to the pattern, counting a swap of two
+// neighbouring characters as one edit, and the end of that substring. If anchored, the substring
+// has to start at the beginning of text. Returns None if the distance exceeds max.
+fn best_match(pattern: &[char], text: &[char], max: usize, anchored: bool) -> Option<(usize, usize)> {
+    // Unless anchored, any position in text may start the match, so the first row is all zeros
+    let first_row: Vec<usize> = (0..=text.len()).map(|j| if anchored { j } else { 0 }).collect();
+    let mut before_previous = first_row.clone();
+    let mut previous = first_row;
+    let mut current = vec![0; text.len() + 1];
+    for i in 1..=pattern.len() {
+        current[0] = i;
+        let mut row_min = current[0];
+        for j in 1..=text.len() {
+            let cost = usize::from(pattern[i - 1] != text[j - 1]);
+            let mut distance = (previous[j] + 1)
+                .min(current[j - 1] + 1)
+                .min(previous[j - 1] + cost);
+            if i > 1 && j > 1 && pattern[i - 1] == text[j - 2] && pattern[i - 2] == text[j - 1] {
+                distance = distance.min(before_previous[j - 2] + 1);
+            }
+            current[j] = distance;
+            row_min = row_min.min(distance);
+        }
+        if row_min > max {
+            return None;
+        }
+        std::mem::swap(&mut before_previous, &mut previous);
+        std::mem::swap(&mut previous, &mut current);
+    }
+    // The earliest end on ties
+    previous
+        .into_iter()
+        .zip(0..)
+        .min()
+        .filter(|&(distance, _)| distance <= max)
+}
+
+fn prepare_case(query: &str, text: &str, case_sensitive: bool) -> (String, String) {
+    if case_sensitive {
+        (query.to_string(), text.to_string())
+    } else {
+        (query.to_lowercase(), text.to_lowercase())
+    }
+}
+
+// Total number of typos needed for every word of the query to appear in the text, or None if
+// some word is too far off. Lower is a better match.
+pub fn fuzzy_distance(query: &str, text: &str, case_sensitive: bool) -> Option<usize> {
+    let (query, text) = prepare_case(query, text, case_sensitive);
+    let text: Vec<char> = text.chars().collect();
+    query.split_whitespace().try_fold(0, |total, word| {
+        let word: Vec<char> = word.chars().collect();
+        best_match(&word, &text, max_distance(word.len()), false).map(|(distance, _)| total + distance)
+    })
+}
+
+// Character ranges start..end of the text matched by each word of the fuzzy query
+pub fn fuzzy_ranges(query: &str, text: &str, case_sensitive: bool) -> Vec<(usize, usize)> {
+    let (query, text) = prepare_case(query, text, case_sensitive);
+    let text: Vec<char> = text.chars().collect();
+    query
+        .split_whitespace()
+        .filter_map(|word| {
+            let word: Vec<char> = word.chars().collect();
+            let max = max_distance(word.len());
+            let (distance, end) = best_match(&word, &text, max, false)?;
+            // Matching the reversed word backwards from the end finds where the match starts
+            let reversed_word: Vec<char> = word.iter().rev().copied().collect();
+            let reversed_text: Vec<char> = text[..end].iter().rev().copied().collect();
+            let (_, length) = best_match(&reversed_word, &reversed_text, distance, true)?;
+            Some((end - length, end))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fuzzy_distance() {
+        assert_eq!(fuzzy_distance("invoice", "recieve_invoice.pdf", false), Some(0));
+        assert_eq!(fuzzy_distance("receive", "recieve_invoice.pdf", false), Some(1));
+        assert_eq!(fuzzy_distance("receive invoice", "recieve_invoice.pdf", false), Some(1));
+        assert_eq!(fuzzy_distance("recive invoce", "Receive_Invoice.pdf", false), Some(2));
+        assert_eq!(fuzzy_distance("Receive", "receive.pdf", true), Some(1));
+        assert_eq!(fuzzy_distance("receipt", "recieve_invoice.pdf", false), None);
+        // Short words have to match exactly
+        assert_eq!(fuzzy_distance("pdg", "invoice.pdf", false), None);
+    }
+
+    #[test]
+    fn test_fuzzy_ranges() {
+        assert_eq!(fuzzy_ranges("receive invoice", "recieve_invoice.pdf", false), vec![(0, 7), (8, 15)]);
+        assert_eq!(fuzzy_ranges("invoce", "my_Invoice.pdf", false), vec![(3, 10)]);
+        assert_eq!(fuzzy_ranges("lyircs", "söng lyrics.pdf", false), vec![(5, 11)]);
+        assert_eq!(fuzzy_ranges("receipt", "recieve_invoice.pdf", false), vec![]);
+    }
+}
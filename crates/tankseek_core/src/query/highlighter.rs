@@ -0,0 +1,130 @@
+use serde::{Deserialize, Serialize};
+
+use crate::file_tree::{ElementId, FileTree};
+use crate::query::fuzzy::fuzzy_ranges;
+use crate::query::query_evaluator::text_regex;
+use crate::query::query_parser::{QueryExpr, QueryLiteral};
+
+// Character ranges start..end that matched the query, in the filename and in the parent path
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Highlights {
+    pub name: Vec<(usize, usize)>,
+    pub path: Vec<(usize, usize)>,
+}
+
+// Finds what matched the query in the filename and, for path: queries, in the parent path of the element
+pub fn highlight(expr: &QueryExpr, tree: &FileTree, index: ElementId) -> Highlights {
+    let filename = tree.get_filename(index);
+    let parent_path = tree.get_full_path(tree.parent(index));
+    // Position of the filename in the full path, after the separator
+    let name_offset = if parent_path.is_empty() {
+        0
+    } else {
+        parent_path.chars().count() + 1
+    };
+
+    let mut highlights = Highlights::default();
+    for literal in positive_literals(expr) {
+        if literal_matches_path(literal) {
+            let full_path = if parent_path.is_empty() {
+                filename.to_string()
+            } else {
+                format!("{}\\{}", parent_path, filename)
+            };
+            for (start, end) in literal_ranges(literal, &full_path) {
+                if start < name_offset {
+                    highlights.path.push((start, end.min(name_offset - 1)));
+                }
+                if end > name_offset {
+                    highlights.name.push((start.max(name_offset) - name_offset, end - name_offset));
+                }
+            }
+        } else {
+            highlights.name.extend(literal_ranges(literal, filename));
+        }
+    }
+    highlights.name = merge_ranges(highlights.name);
+    highlights.path = merge_ranges(highlights.path);
+    highlights
+}
+
+// The literals results have to contain, negated ones can't be highlighted
+fn positive_literals(expr: &QueryExpr) -> Vec<&QueryLiteral> {
+    match expr {
+        QueryExpr::Literal(literal) => vec![literal],
+        QueryExpr::And(left, right) | QueryExpr::Or(left, right) => {
+            let mut literals = positive_literals(left);
+            literals.extend(positive_literals(right));
+            literals
+        }
+        _ => Vec::new(),
+    }
+}
+
+fn literal_matches_path(literal: &QueryLiteral) -> bool {
+    match literal {
+        QueryLiteral::Text(text) => text.match_path,
+        QueryLiteral::Regex(regex) => regex.match_path,
+    }
+}
+
+// Character ranges of the text matched by the literal
+fn literal_ranges(literal: &QueryLiteral, text: &str) -> Vec<(usize, usize)> {
+    let regex = match literal {
+        QueryLiteral::Text(query) if query.text.is_empty() => return Vec::new(),
+        QueryLiteral::Text(query) if query.fuzzy => {
+            return fuzzy_ranges(&query.text, text, query.case_sensitive);
+        }
+        QueryLiteral::Text(query) => match text_regex(query) {
+            Some(regex) => regex,
+            None => return Vec::new(),
+        },
+        QueryLiteral::Regex(query) => query.pattern.clone(),
+    };
+    regex
+        .find_iter(text)
+        .filter(|found| !found.is_empty())
+        .map(|found| {
+            let start = text[..found.start()].chars().count();
+            (start, start + found.as_str().chars().count())
+        })
+        .collect()
+}
+
+// Sorts the ranges and joins overlapping or touching ones
+fn merge_ranges(mut ranges: Vec<(usize, usize)>) -> Vec<(usize, usize)> {
+    ranges.sort_unstable();
+    let mut merged: Vec<(usize, usize)> = Vec::with_capacity(ranges.len());
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::query_parser::parse_query;
+
+    #[test]
+    fn test_highlight() {
+        let mut tree = FileTree::with_capacity(5);
+        let file = tree.add_or_update_recursive("C:\\Docs\\Song Lyrics song.txt", None, None, None, 0);
+        let highlight_query = |query: &str| highlight(&parse_query(query), &tree, file);
+
+        assert_eq!(highlight_query("song").name, vec![(0, 4), (12, 16)]);
+        assert_eq!(highlight_query("case:song").name, vec![(12, 16)]);
+        assert_eq!(highlight_query("lyr | ics").name, vec![(5, 11)]);
+        assert_eq!(highlight_query("song !lyrics").name, vec![(0, 4), (12, 16)]);
+        assert_eq!(highlight_query("fuzzy:lyircs").name, vec![(5, 11)]);
+        assert_eq!(highlight_query("regex:^s.ng").name, vec![(0, 4)]);
+        assert_eq!(highlight_query("size:>10"), Highlights::default());
+
+        let path_highlights = highlight_query("path:docs\\song");
+        assert_eq!(path_highlights.path, vec![(3, 7)]);
+        assert_eq!(path_highlights.name, vec![(0, 4)]);
+    }
+}
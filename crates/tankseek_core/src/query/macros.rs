@@ -0,0 +1,129 @@
+use std::{error::Error, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+// Placeholder for the macro argument in an expansion, as in Everything's filter macros
+pub const MACRO_PARAM: &str = "$param:";
+
+// A user-defined named filter like src: = ext:rs;toml;md !path:target
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueryMacro {
+    pub name: String,
+    pub expansion: String,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+impl QueryMacro {
+    pub fn new<T: Into<String>, U: Into<String>>(name: T, expansion: U) -> Self {
+        QueryMacro {
+            name: name.into(),
+            expansion: expansion.into(),
+            description: None,
+        }
+    }
+
+    pub fn takes_argument(&self) -> bool {
+        self.expansion.contains(MACRO_PARAM)
+    }
+
+    // Returns the query text the macro stands for, with the argument filled in
+    pub fn expand(&self, argument: Option<&str>) -> String {
+        let argument = argument.unwrap_or("").replace('"', "");
+        if !argument.contains(char::is_whitespace) {
+            return self.expansion.replace(MACRO_PARAM, &argument);
+        }
+        // Quote the value holding the argument so it stays one term, e.g. path:"\projects\my app"
+        self.expansion
+            .split(' ')
+            .map(|word| match word.find(MACRO_PARAM) {
+                Some(param_start) => {
+                    let value_start = word[..param_start].rfind(':').map_or(0, |colon| colon + 1);
+                    let (prefix, value) = word.split_at(value_start);
+                    format!("{}\"{}\"", prefix, value.replace(MACRO_PARAM, &argument))
+                }
+                None => word.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+// Loads macros from a JSON file containing a list of {"name", "expansion", "description"} objects
+pub fn load_macros<P: AsRef<Path>>(filepath: P) -> Result<Vec<QueryMacro>, Box<dyn Error>> {
+    let file = std::fs::File::open(filepath)?;
+    let macros: Vec<QueryMacro> = serde_json::from_reader(std::io::BufReader::new(file))?;
+    Ok(macros)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::query_parser::{QueryParserOptions, parse_query, parse_query_with_options};
+
+    fn options() -> QueryParserOptions {
+        QueryParserOptions {
+            macros: vec![
+                QueryMacro::new("src", "ext:rs;toml;md !path:target"),
+                QueryMacro::new("proj", "path:\\projects\\$param:"),
+                QueryMacro::new("rust", "src: case:Cargo | rust"),
+                QueryMacro::new("loop", "loop: x"),
+            ],
+            ..QueryParserOptions::default()
+        }
+    }
+
+    fn assert_expands_to(query: &str, expanded: &str) {
+        assert_eq!(
+            format!("{:?}", parse_query_with_options(query, &options())),
+            format!("{:?}", parse_query(expanded))
+        );
+    }
+
+    #[test]
+    fn test_expand() {
+        let proj = QueryMacro::new("proj", "path:\\projects\\$param:");
+        assert!(proj.takes_argument());
+        assert_eq!(proj.expand(Some("foo")), "path:\\projects\\foo");
+        assert_eq!(proj.expand(None), "path:\\projects\\");
+        assert_eq!(proj.expand(Some("my app")), "path:\"\\projects\\my app\"");
+        assert!(!QueryMacro::new("src", "ext:rs").takes_argument());
+    }
+
+    #[test]
+    fn test_macro_queries() {
+        assert_expands_to("src:", "< ext:rs;toml;md !path:target >");
+        assert_expands_to("SRC: main", "< ext:rs;toml;md !path:target > main");
+        assert_expands_to("proj:foo readme", "path:\\projects\\foo readme");
+        assert_expands_to("proj:\"my app\"", "path:\"\\projects\\my app\"");
+        assert_expands_to("!src:", "!< ext:rs;toml;md !path:target >");
+        // Macros may use other macros
+        assert_expands_to("rust:", "< < ext:rs;toml;md !path:target > case:Cargo | rust >");
+        // A macro is not expanded inside itself
+        assert_expands_to("loop:", "< loop: x >");
+        // Without a definition the name is plain text
+        assert_eq!(
+            format!("{:?}", parse_query("src:")),
+            format!("{:?}", parse_query("\"src:\""))
+        );
+    }
+
+    #[test]
+    fn test_load_macros() {
+        let path = std::env::temp_dir().join("tankseek_test_macros.json");
+        std::fs::write(
+            &path,
+            r#"[
+                {"name": "src", "expansion": "ext:rs;toml;md !path:target", "description": "Source files"},
+                {"name": "proj", "expansion": "path:\\projects\\$param:"}
+            ]"#,
+        )
+        .unwrap();
+        let macros = load_macros(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(macros.len(), 2);
+        assert_eq!(macros[0].description.as_deref(), Some("Source files"));
+        assert_eq!(macros[1], QueryMacro::new("proj", "path:\\projects\\$param:"));
+    }
+}
@@ -1,3 +1,8 @@
+pub mod completion;
 pub mod date;
+pub mod fuzzy;
+pub mod highlighter;
+pub mod query_evaluator;
 pub mod query_parser;
 pub mod lexer;
+pub mod macros;
@@ -0,0 +1,553 @@
+use chrono::{Datelike, Local, TimeZone};
+
+use crate::file_tree::{ElementId, FileTree};
+use crate::file_type::FileCategory;
+use crate::indexer::bigram_index::BigramIndex;
+use crate::indexer::postings::{BitmapPostings, Postings};
+use crate::indexer::prefix_index::{PrefixIndex, starts_with_ignore_case};
+use crate::indexer::text_index::TextIndex;
+use crate::query::fuzzy::{fuzzy_distance, max_distance};
+use crate::query::query_parser::*;
+
+// Each fuzzy word keeps at most this many candidates, those sharing the most bigrams with it
+const MAX_FUZZY_CANDIDATES: usize = 10_000;
+
+// Seconds between the FILETIME epoch (1601-01-01) and the unix epoch
+const FILETIME_UNIX_EPOCH_OFFSET: i64 = 11_644_473_600;
+
+// Converts a FILETIME (100ns intervals since 1601-01-01) as stored in EFU files to a unix timestamp
+pub fn filetime_to_unix(filetime: i64) -> i64 {
+    filetime.div_euclid(10_000_000) - FILETIME_UNIX_EPOCH_OFFSET
+}
+
+pub struct QueryEvaluator<'a> {
+    tree: &'a FileTree,
+    bigram_index: &'a BigramIndex,
+    prefix_index: Option<&'a PrefixIndex>,
+    ngram_index: Option<&'a dyn TextIndex>,
+}
+
+impl<'a> QueryEvaluator<'a> {
+    pub fn new(tree: &'a FileTree, bigram_index: &'a BigramIndex) -> Self {
+        QueryEvaluator {
+            tree,
+            bigram_index,
+            prefix_index: None,
+            ngram_index: None,
+        }
+    }
+
+    // Looks up texts at least as long as its grams in a more selective index than the bigrams
+    pub fn with_ngram_index(mut self, ngram_index: &'a dyn TextIndex) -> Self {
+        self.ngram_index = Some(ngram_index);
+        self
+    }
+
+    // Answers startwith: from the prefix index instead of checking every element
+    pub fn with_prefix_index(mut self, prefix_index: &'a PrefixIndex) -> Self {
+        self.prefix_index = Some(prefix_index);
+        self
+    }
+
+    // Returns the sorted indices of all elements matching the expression. The root element and
+    // removed elements are never matched.
+    pub fn evaluate(&self, expr: &QueryExpr) -> Vec<ElementId> {
+        let mut indices = self.evaluate_within(expr, None);
+        if self.tree.has_removed() {
+            indices.retain(|&index| !self.tree.is_removed(index));
+        }
+        indices
+    }
+
+    // Evaluates the expression on the given sorted candidates, or on all elements if None
+    fn evaluate_within(&self, expr: &QueryExpr, candidates: Option<&[ElementId]>) -> Vec<ElementId> {
+        match expr {
+            QueryExpr::Or(_, _) | QueryExpr::Not(_) if candidates.is_none() && combines_index_terms(expr) => {
+                self.evaluate_postings(expr).to_vec()
+            }
+            QueryExpr::And(left, right) => {
+                // Start with the side that can use the index, the other side only filters its results
+                let (first, second) = if !uses_index(left) && uses_index(right) {
+                    (right, left)
+                } else {
+                    (left, right)
+                };
+                let first_result = self.evaluate_within(first, candidates);
+                self.evaluate_within(second, Some(&first_result))
+            }
+            QueryExpr::Or(left, right) => union(
+                &self.evaluate_within(left, candidates),
+                &self.evaluate_within(right, candidates),
+            ),
+            QueryExpr::Not(inner) => {
+                let excluded = self.evaluate_within(inner, candidates);
+                self.filter(candidates, |index| excluded.binary_search(&index).is_err())
+            }
+            QueryExpr::Literal(QueryLiteral::Text(text)) => self.evaluate_text(text, candidates),
+            QueryExpr::Literal(QueryLiteral::Regex(regex)) => self.filter(candidates, |index| {
+                if regex.match_path {
+                    regex.pattern.is_match(&self.tree.get_full_path(index))
+                } else {
+                    regex.pattern.is_match(self.tree.get_filename(index))
+                }
+            }),
+            QueryExpr::Function(function) => self.evaluate_function(function, candidates),
+        }
+    }
+
+    // Evaluates unions and negations of index backed terms on postings, so dense intermediate
+    // results are combined as bitmaps
+    fn evaluate_postings(&self, expr: &QueryExpr) -> Postings {
+        match expr {
+            QueryExpr::Or(left, right) => self.evaluate_postings(left).union(&self.evaluate_postings(right)),
+            QueryExpr::Not(inner) => {
+                let all = Postings::Bitmap(BitmapPostings::from_sorted_iter(self.tree.ids().skip(1)));
+                all.difference(&self.evaluate_postings(inner))
+            }
+            expr => Postings::new(self.evaluate_within(expr, None), self.tree.len()),
+        }
+    }
+
+    // Functions on folders or sub-queries resolve them once, the others check each candidate
+    fn evaluate_function(&self, function: &QueryFunction, candidates: Option<&[ElementId]>) -> Vec<ElementId> {
+        match function {
+            QueryFunction::Size(cmp, start, end) => self.filter(candidates, |index| {
+                self.tree
+                    .size(index)
+                    .is_some_and(|value| compare(cmp, value, *start as i64, *end as i64))
+            }),
+            QueryFunction::DateModified(cmp, date) => {
+                self.filter(candidates, |index| matches_date(cmp, date, self.tree.date_modified(index)))
+            }
+            QueryFunction::DateCreated(cmp, date) => {
+                self.filter(candidates, |index| matches_date(cmp, date, self.tree.date_created(index)))
+            }
+            QueryFunction::Parent(path) | QueryFunction::NoSubfolders(path) => {
+                self.evaluate_folder(path, false, candidates)
+            }
+            QueryFunction::InFolder(path) => self.evaluate_folder(path, true, candidates),
+            QueryFunction::Ext(exts) => self.filter(candidates, |index| {
+                match self.tree.get_filename(index).rsplit_once('.') {
+                    Some((_, ext)) => exts.iter().any(|e| e.eq_ignore_ascii_case(ext)),
+                    None => false,
+                }
+            }),
+            QueryFunction::StartWith(prefix) => match (candidates, self.prefix_index) {
+                (None, Some(prefix_index)) => prefix_index.query_prefix(self.tree, prefix),
+                _ => self.filter(candidates, |index| {
+                    starts_with_ignore_case(self.tree.get_filename(index), prefix)
+                }),
+            },
+            QueryFunction::Child(inner) => self.evaluate_containing(inner, false, candidates),
+            QueryFunction::Descendant(inner) => self.evaluate_containing(inner, true, candidates),
+            QueryFunction::Category(category) => self.filter(candidates, |index| {
+                FileCategory::of_element(self.tree.get(index).unwrap()) == *category
+            }),
+        }
+    }
+
+    fn filter<F: Fn(ElementId) -> bool>(
+        &self,
+        candidates: Option<&[ElementId]>,
+        predicate: F,
+    ) -> Vec<ElementId> {
+        match candidates {
+            Some(candidates) => candidates.iter().copied().filter(|&index| predicate(index)).collect(),
+            None => self.tree.ids().skip(1).filter(|&index| predicate(index)).collect(),
+        }
+    }
+
+    fn evaluate_text(&self, text: &TextQuery, candidates: Option<&[ElementId]>) -> Vec<ElementId> {
+        let index_candidates;
+        let candidates = match candidates {
+            None if !text.text.is_empty() && !text.match_path => {
+                index_candidates = if text.fuzzy {
+                    self.fuzzy_candidates(&text.text)
+                } else {
+                    self.index_candidates(&text.text)
+                };
+                Some(index_candidates.as_slice())
+            }
+            candidates => candidates,
+        };
+
+        let matches_type = |index: ElementId| {
+            if self.tree.is_folder(index) {
+                !text.file_only
+            } else {
+                !text.folder_only
+            }
+        };
+        if text.fuzzy {
+            return self.filter(candidates, |index| {
+                matches_type(index)
+                    && fuzzy_distance(&text.text, &self.text_of(text, index), text.case_sensitive).is_some()
+            });
+        }
+
+        let Some(regex) = text_regex(text) else {
+            return Vec::new();
+        };
+        self.filter(candidates, |index| {
+            matches_type(index) && regex.is_match(&self.text_of(text, index))
+        })
+    }
+
+    // Elements in the folder at the path, only its direct children unless recursive
+    fn evaluate_folder(&self, path: &str, recursive: bool, candidates: Option<&[ElementId]>) -> Vec<ElementId> {
+        let Some(folder) = self.tree.find_path(path) else {
+            return Vec::new();
+        };
+        match candidates {
+            Some(_) => self.filter(candidates, |index| self.is_in_folder(index, folder, recursive)),
+            None if recursive => {
+                // The descendants form a contiguous range in pre-order
+                let mut indices = match self.tree.descendants(folder) {
+                    Some(descendants) => descendants.to_vec(),
+                    None => self.tree.collect_all_children(folder),
+                };
+                indices.sort_unstable();
+                indices
+            }
+            None => {
+                let mut indices = self.tree.children(folder).to_vec();
+                indices.sort_unstable();
+                indices
+            }
+        }
+    }
+
+    // Folders containing an element matching the query, directly or anywhere below them if recursive
+    fn evaluate_containing(
+        &self,
+        inner: &QueryExpr,
+        recursive: bool,
+        candidates: Option<&[ElementId]>,
+    ) -> Vec<ElementId> {
+        let mut is_container = vec![false; self.tree.len()];
+        for index in self.evaluate(inner) {
+            let mut parent = self.tree.parent(index);
+            // Stop at the root or at a folder already marked, its ancestors are marked too
+            while parent != ElementId::ROOT && !is_container[parent.index()] {
+                is_container[parent.index()] = true;
+                if !recursive {
+                    break;
+                }
+                parent = self.tree.parent(parent);
+            }
+        }
+        self.filter(candidates, |index| is_container[index.index()])
+    }
+
+    fn is_in_folder(&self, index: ElementId, folder: ElementId, recursive: bool) -> bool {
+        if recursive {
+            self.tree.is_descendant(index, folder)
+        } else {
+            index != ElementId::ROOT && self.tree.parent(index) == folder
+        }
+    }
+
+    // The part of the element a text query is matched against
+    fn text_of(&self, text: &TextQuery, index: ElementId) -> std::borrow::Cow<'_, str> {
+        if text.match_path {
+            self.tree.get_full_path(index).into()
+        } else {
+            self.tree.get_filename(index).into()
+        }
+    }
+
+    // Elements sharing enough bigrams with every word of the text to be within its typo budget
+    fn fuzzy_candidates(&self, text: &str) -> Vec<ElementId> {
+        let text = text.to_lowercase();
+        let mut result: Option<Vec<ElementId>> = None;
+        for word in text.split_whitespace() {
+            let word_len = word.chars().count();
+            let mut indices = if word_len < 2 {
+                self.index_candidates(word)
+            } else {
+                // Each typo changes at most three bigrams of the word (a swap of two characters)
+                let bigrams = word_len - 1;
+                let min_shared = bigrams.saturating_sub(3 * max_distance(word_len)).max(1);
+                self.bigram_index.query_overlap(word, min_shared, MAX_FUZZY_CANDIDATES)
+            };
+            if indices.first() == Some(&ElementId::ROOT) {
+                indices.remove(0);
+            }
+            result = Some(match result {
+                Some(result) => intersect(&result, &indices),
+                None => indices,
+            });
+        }
+        result.unwrap_or_default()
+    }
+
+    // Elements whose filename may contain the text according to the n-gram or bigram index
+    fn index_candidates(&self, text: &str) -> Vec<ElementId> {
+        let text = text.to_lowercase();
+        let mut indices = match self.ngram_index {
+            Some(ngram_index) if text.chars().count() >= ngram_index.gram_size() => {
+                ngram_index.candidates(&text)
+            }
+            _ => self.bigram_index.candidates(&text),
+        };
+        // The root element is not part of any result
+        if indices.first() == Some(&ElementId::ROOT) {
+            indices.remove(0);
+        }
+        indices
+    }
+}
+
+// Whether evaluating the expression on all elements can use an index instead of a full scan
+fn uses_index(expr: &QueryExpr) -> bool {
+    match expr {
+        QueryExpr::Literal(QueryLiteral::Text(text)) => !text.text.is_empty() && !text.match_path,
+        QueryExpr::Function(
+            QueryFunction::StartWith(_)
+            | QueryFunction::Parent(_)
+            | QueryFunction::InFolder(_)
+            | QueryFunction::NoSubfolders(_),
+        ) => true,
+        QueryExpr::Function(QueryFunction::Child(inner) | QueryFunction::Descendant(inner)) => {
+            uses_index(inner)
+        }
+        QueryExpr::And(left, right) => uses_index(left) || uses_index(right),
+        QueryExpr::Or(left, right) => uses_index(left) && uses_index(right),
+        _ => false,
+    }
+}
+
+// Whether the expression only combines index backed terms with | and !
+fn combines_index_terms(expr: &QueryExpr) -> bool {
+    match expr {
+        QueryExpr::Or(left, right) => combines_index_terms(left) && combines_index_terms(right),
+        QueryExpr::Not(inner) => combines_index_terms(inner),
+        expr => uses_index(expr),
+    }
+}
+
+// Builds the regex a filename (or path) must match for the text query
+pub(crate) fn text_regex(text: &TextQuery) -> Option<regex::Regex> {
+    let mut pattern = regex::escape(&text.text);
+    if text.whole_filename {
+        pattern = format!("^{}$", pattern);
+    } else if text.whole_word {
+        pattern = format!(r"\b{}\b", pattern);
+    }
+    regex::RegexBuilder::new(&pattern)
+        .case_insensitive(!text.case_sensitive)
+        .build()
+        .ok()
+}
+
+// Compares a value against the inclusive range start..=end of the query
+fn compare(cmp: &QueryCmp, value: i64, start: i64, end: i64) -> bool {
+    match cmp {
+        QueryCmp::Eq | QueryCmp::Range => start <= value && value <= end,
+        QueryCmp::Gt => value > end,
+        QueryCmp::Ge => value >= start,
+        QueryCmp::Lt => value < start,
+        QueryCmp::Le => value <= end,
+    }
+}
+
+fn matches_date(cmp: &QueryCmp, date: &QueryDate, filetime: Option<i64>) -> bool {
+    let Some(filetime) = filetime else {
+        return *date == QueryDate::Unknown;
+    };
+    let timestamp = filetime_to_unix(filetime);
+    match date {
+        QueryDate::Unknown => false,
+        // Range(0, 0) is an unparsable date
+        QueryDate::Range(0, 0) => false,
+        QueryDate::Range(start, end) => compare(cmp, timestamp, *start, *end),
+        QueryDate::Weekday(weekday) => Local.timestamp_opt(timestamp, 0).single().is_some_and(|datetime| {
+            let day = datetime.weekday().num_days_from_sunday() as i64;
+            compare(cmp, day, *weekday as i64, *weekday as i64)
+        }),
+        QueryDate::Month(month) => Local.timestamp_opt(timestamp, 0).single().is_some_and(|datetime| {
+            compare(cmp, datetime.month() as i64, *month as i64, *month as i64)
+        }),
+    }
+}
+
+// Intersects two sorted lists of indices
+fn intersect(left: &[ElementId], right: &[ElementId]) -> Vec<ElementId> {
+    let mut result = Vec::with_capacity(left.len().min(right.len()));
+    let (mut i, mut j) = (0, 0);
+    while i < left.len() && j < right.len() {
+        if left[i] < right[j] {
+            i += 1;
+        } else if left[i] > right[j] {
+            j += 1;
+        } else {
+            result.push(left[i]);
+            i += 1;
+            j += 1;
+        }
+    }
+    result
+}
+
+// Merges two sorted lists of indices
+fn union(left: &[ElementId], right: &[ElementId]) -> Vec<ElementId> {
+    let mut result = Vec::with_capacity(left.len() + right.len());
+    let (mut i, mut j) = (0, 0);
+    while i < left.len() && j < right.len() {
+        if left[i] < right[j] {
+            result.push(left[i]);
+            i += 1;
+        } else if left[i] > right[j] {
+            result.push(right[j]);
+            j += 1;
+        } else {
+            result.push(left[i]);
+            i += 1;
+            j += 1;
+        }
+    }
+    result.extend_from_slice(&left[i..]);
+    result.extend_from_slice(&right[j..]);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indexer::text_index::NgramIndex;
+
+    // 2024-03-01 12:00:00 UTC as FILETIME
+    const MARCH_2024: i64 = (1_709_294_400 + FILETIME_UNIX_EPOCH_OFFSET) * 10_000_000;
+
+    fn create_tree() -> FileTree {
+        let mut tree = FileTree::with_capacity(10);
+        tree.add_or_update_recursive("C:\\music\\Song.mp3", Some(5000), Some(MARCH_2024), None, 0);
+        tree.add_or_update_recursive("C:\\music\\cover.jpg", Some(200), None, None, 0);
+        tree.add_or_update_recursive("C:\\docs\\notes.txt", Some(10), Some(MARCH_2024), None, 0);
+        tree.add_or_update_recursive("C:\\docs\\song lyrics.pdf", Some(300), None, None, 0);
+        tree.add_or_update_recursive("C:\\docs\\archive", None, None, None, 0x10);
+        tree
+    }
+
+    fn search(tree: &FileTree, query: &str) -> Vec<String> {
+        let index = BigramIndex::new(tree);
+        let evaluator = QueryEvaluator::new(tree, &index);
+        let mut names: Vec<String> = evaluator
+            .evaluate(&parse_query(query))
+            .into_iter()
+            .map(|index| tree.get_filename(index).to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_text_queries() {
+        let tree = create_tree();
+        assert_eq!(search(&tree, "song"), vec!["Song.mp3", "song lyrics.pdf"]);
+        assert_eq!(search(&tree, "case:song"), vec!["song lyrics.pdf"]);
+        assert_eq!(
+            search(&tree, "s"),
+            vec!["Song.mp3", "docs", "music", "notes.txt", "song lyrics.pdf"]
+        );
+        assert_eq!(search(&tree, "wfn:notes.txt"), vec!["notes.txt"]);
+        assert_eq!(search(&tree, "wfn:notes"), Vec::<String>::new());
+        assert_eq!(search(&tree, "folder:c"), vec!["C:", "archive", "docs", "music"]);
+        assert_eq!(search(&tree, "file:c"), vec!["cover.jpg", "song lyrics.pdf"]);
+        assert_eq!(search(&tree, "path:docs\\notes"), vec!["notes.txt"]);
+
+        // The same results with a trigram index
+        let bigram_index = BigramIndex::new(&tree);
+        let trigram_index = NgramIndex::new(&tree, 3);
+        let evaluator = QueryEvaluator::new(&tree, &bigram_index).with_ngram_index(&trigram_index);
+        for query in ["song", "so", "s", "ng.mp3", "case:song", "wfn:notes.txt", "xyz"] {
+            let expr = parse_query(query);
+            assert_eq!(evaluator.evaluate(&expr), QueryEvaluator::new(&tree, &bigram_index).evaluate(&expr));
+        }
+    }
+
+    #[test]
+    fn test_boolean_queries() {
+        let tree = create_tree();
+        assert_eq!(search(&tree, "song !pdf"), vec!["Song.mp3"]);
+        assert_eq!(search(&tree, "notes | cover"), vec!["cover.jpg", "notes.txt"]);
+        assert_eq!(search(&tree, "s !file:"), vec!["docs", "music"]);
+        assert_eq!(search(&tree, "file: s"), vec!["Song.mp3", "notes.txt", "song lyrics.pdf"]);
+        assert_eq!(search(&tree, "< notes | cover > size:>100"), vec!["cover.jpg"]);
+        assert_eq!(search(&tree, "notes | cover size:>100"), vec!["cover.jpg", "notes.txt"]);
+        assert_eq!(search(&tree, "!s"), vec!["C:", "archive", "cover.jpg"]);
+        assert_eq!(search(&tree, "notes | !< s | cover >"), vec!["C:", "archive", "notes.txt"]);
+    }
+
+    #[test]
+    fn test_function_queries() {
+        let tree = create_tree();
+        assert_eq!(search(&tree, "size:>250"), vec!["Song.mp3", "song lyrics.pdf"]);
+        assert_eq!(search(&tree, "size:tiny"), vec!["Song.mp3", "cover.jpg", "notes.txt", "song lyrics.pdf"]);
+        assert_eq!(search(&tree, "size:>tiny"), Vec::<String>::new());
+        assert_eq!(search(&tree, "ext:jpg;pdf"), vec!["cover.jpg", "song lyrics.pdf"]);
+        assert_eq!(search(&tree, "startwith:SO"), vec!["Song.mp3", "song lyrics.pdf"]);
+        assert_eq!(search(&tree, "startwith:\"song l\""), vec!["song lyrics.pdf"]);
+
+        let bigram_index = BigramIndex::new(&tree);
+        let prefix_index = PrefixIndex::new(&tree, &crate::sorter::Sorter::new());
+        let evaluator = QueryEvaluator::new(&tree, &bigram_index).with_prefix_index(&prefix_index);
+        assert_eq!(
+            evaluator.evaluate(&parse_query("startwith:so | startwith:c")),
+            evaluator.evaluate(&parse_query("< regex:^so | regex:^c >"))
+        );
+        assert_eq!(search(&tree, "parent:c:/docs"), vec!["archive", "notes.txt", "song lyrics.pdf"]);
+        assert_eq!(search(&tree, "parent:C:\\"), vec!["docs", "music"]);
+        assert_eq!(search(&tree, "nosubfolders:C:"), vec!["docs", "music"]);
+        assert_eq!(
+            search(&tree, "infolder:C:\\"),
+            vec!["Song.mp3", "archive", "cover.jpg", "docs", "music", "notes.txt", "song lyrics.pdf"]
+        );
+        assert_eq!(search(&tree, "infolder:c:/ song"), vec!["Song.mp3", "song lyrics.pdf"]);
+        assert_eq!(search(&tree, "parent:c: song"), Vec::<String>::new());
+        assert_eq!(search(&tree, "parent:\"C:\\docs\\\" song"), vec!["song lyrics.pdf"]);
+        assert_eq!(search(&tree, "parent:c:\\missing"), Vec::<String>::new());
+        assert_eq!(search(&tree, "!infolder:c:\\docs file:"), vec!["Song.mp3", "cover.jpg"]);
+        assert_eq!(search(&tree, "dm:2024"), vec!["Song.mp3", "notes.txt"]);
+        assert_eq!(search(&tree, "dm:<2024"), Vec::<String>::new());
+        assert_eq!(search(&tree, "dm:unknown file:"), vec!["cover.jpg", "song lyrics.pdf"]);
+    }
+
+    #[test]
+    fn test_containing_queries() {
+        let mut tree = create_tree();
+        tree.add_or_update_recursive("C:\\code\\app\\package.json", Some(10), None, None, 0);
+        tree.add_or_update_recursive("C:\\code\\lib\\src\\logo.psd", Some(10), None, None, 0);
+        tree.add_or_update_recursive("C:\\code\\lib\\src\\Cargo.toml", Some(10), None, None, 0);
+        assert_eq!(search(&tree, "child:package.json"), vec!["app"]);
+        assert_eq!(search(&tree, "descendant:package.json"), vec!["C:", "app", "code"]);
+        assert_eq!(search(&tree, "child:ext:psd"), vec!["src"]);
+        assert_eq!(search(&tree, "descendant:ext:psd"), vec!["C:", "code", "lib", "src"]);
+        assert_eq!(search(&tree, "descendant:< ext:psd | ext:json > !descendant:cargo"), vec!["app"]);
+        assert_eq!(search(&tree, "child:song size:>10"), Vec::<String>::new());
+        assert_eq!(search(&tree, "child:< song file: >"), vec!["docs", "music"]);
+        assert_eq!(search(&tree, "s child:notes"), vec!["docs"]);
+    }
+
+    #[test]
+    fn test_category_macros() {
+        let tree = create_tree();
+        assert_eq!(search(&tree, "audio:"), vec!["Song.mp3"]);
+        assert_eq!(search(&tree, "pic:"), vec!["cover.jpg"]);
+        assert_eq!(search(&tree, "doc:"), vec!["notes.txt", "song lyrics.pdf"]);
+        assert_eq!(search(&tree, "doc:song"), vec!["song lyrics.pdf"]);
+        assert_eq!(search(&tree, "doc: song"), vec!["song lyrics.pdf"]);
+        assert_eq!(search(&tree, "video: | zip:"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_fuzzy_queries() {
+        let mut tree = create_tree();
+        tree.add_or_update_recursive("C:\\docs\\recieve_invoice.pdf", Some(10), None, None, 0);
+        assert_eq!(search(&tree, "receive"), Vec::<String>::new());
+        assert_eq!(search(&tree, "fuzzy:\"receive invoice\""), vec!["recieve_invoice.pdf"]);
+        assert_eq!(search(&tree, "fuzzy:lyircs"), vec!["song lyrics.pdf"]);
+        assert_eq!(search(&tree, "fuzzy:< sogn | notse > file:"), vec!["Song.mp3", "notes.txt", "song lyrics.pdf"]);
+        assert_eq!(search(&tree, "ext:pdf fuzzy:invoce"), vec!["recieve_invoice.pdf"]);
+    }
+}
@@ -1,9 +1,17 @@
-use std::fmt::format;
-
-use crate::query::date::*;
+use crate::file_type::FileCategory;
+use crate::query::date::DateOptions;
 use crate::query::lexer;
+use crate::query::macros::QueryMacro;
+
+/// Options controlling how queries are parsed
+#[derive(Debug, Clone, Default)]
+pub struct QueryParserOptions {
+    pub date: DateOptions,
+    // User-defined macros, expanded while parsing
+    pub macros: Vec<QueryMacro>,
+}
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
 pub struct QueryModifiersTracking {
     pub case_sensitive: bool,
     pub diacritics_sensitive: bool,
@@ -14,22 +22,7 @@ pub struct QueryModifiersTracking {
     pub whole_filename: bool,
     pub whole_word: bool,
     pub wildcards: bool,
-}
-
-impl Default for QueryModifiersTracking {
-    fn default() -> Self {
-        QueryModifiersTracking {
-            case_sensitive: false,
-            diacritics_sensitive: false,
-            file_only: false,
-            folder_only: false,
-            match_path: false,
-            regex: false,
-            whole_filename: false,
-            whole_word: false,
-            wildcards: false,
-        }
-    }
+    pub fuzzy: bool,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -42,6 +35,8 @@ pub struct TextQuery {
     pub match_path: bool,
     pub whole_filename: bool,
     pub whole_word: bool,
+    // Tolerate typos, matching by edit distance
+    pub fuzzy: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -51,14 +46,23 @@ pub struct RegexQuery {
     pub diacritics_sensitive: bool,
     pub match_path: bool,
 }
+// Regex has no PartialEq, queries with the same pattern are equal
+impl PartialEq for RegexQuery {
+    fn eq(&self, other: &Self) -> bool {
+        self.pattern.as_str() == other.pattern.as_str()
+            && self.case_sensitive == other.case_sensitive
+            && self.diacritics_sensitive == other.diacritics_sensitive
+            && self.match_path == other.match_path
+    }
+}
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum QueryLiteral {
     Text(TextQuery),
     Regex(RegexQuery),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum QueryExpr {
     Literal(QueryLiteral),
     Function(QueryFunction),
@@ -69,13 +73,30 @@ pub enum QueryExpr {
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum QueryFunction {
-    Size(QueryCmp, u64),
+    Size(QueryCmp, u64, u64), // inclusive range start..=end, a single number has start == end
     DateModified(QueryCmp, QueryDate),
     DateCreated(QueryCmp, QueryDate),
-    Parent(String),
+    Parent(String),       // direct children of the folder
+    InFolder(String),     // everything below the folder, including subfolders
+    NoSubfolders(String), // like parent:, the folder's own files and folders without their contents
     Ext(Vec<String>),
+    StartWith(String),
+    Child(Box<QueryExpr>),      // folders with a direct child matching the query
+    Descendant(Box<QueryExpr>), // folders with anything below them matching the query
+    Category(FileCategory),
 }
 
+// Size keywords like size:large, as in Everything. Inclusive byte ranges.
+pub const SIZE_KEYWORDS: &[(&str, u64, u64)] = &[
+    ("empty", 0, 0),
+    ("tiny", 1, 10 << 10),
+    ("small", (10 << 10) + 1, 100 << 10),
+    ("medium", (100 << 10) + 1, 1 << 20),
+    ("large", (1 << 20) + 1, 16 << 20),
+    ("huge", (16 << 20) + 1, 128 << 20),
+    ("gigantic", (128 << 20) + 1, i64::MAX as u64),
+];
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum QueryCmp {
     Eq,
@@ -132,6 +153,21 @@ pub enum QueryDate {
     Unknown,
 }
 
+impl QueryExpr {
+    // The text queries that results have to match, used for ranking. Negated terms are skipped.
+    pub fn text_terms(&self) -> Vec<&TextQuery> {
+        match self {
+            QueryExpr::Literal(QueryLiteral::Text(text)) if !text.text.is_empty() => vec![text],
+            QueryExpr::And(left, right) | QueryExpr::Or(left, right) => {
+                let mut terms = left.text_terms();
+                terms.extend(right.text_terms());
+                terms
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
 fn exprs_to_and(exprs: Vec<QueryExpr>) -> QueryExpr {
     if exprs.is_empty() {
         return QueryExpr::Literal(QueryLiteral::Text(TextQuery {
@@ -143,6 +179,7 @@ fn exprs_to_and(exprs: Vec<QueryExpr>) -> QueryExpr {
             match_path: false,
             whole_filename: false,
             whole_word: false,
+            fuzzy: false,
         }));
     }
     let mut iter = exprs.into_iter();
@@ -182,6 +219,24 @@ fn get_comparison(lexer: &mut lexer::QueryLexer) -> Option<QueryCmp> {
     }
 }
 
+// Reads a function argument, joining adjacent tokens so values like "today..14:00" stay intact
+fn read_argument(lexer: &mut lexer::QueryLexer) -> Option<String> {
+    let mut argument = match lexer.next_token()? {
+        lexer::QueryToken::Ident(arg) | lexer::QueryToken::StrLit(arg) => arg,
+        _ => return None,
+    };
+    while let Some(token) = lexer.peek_token() {
+        match token {
+            lexer::QueryToken::Whitespace | lexer::QueryToken::Or => break,
+            _ => {
+                lexer.next_token();
+                argument.push_str(&token.to_string());
+            }
+        }
+    }
+    Some(argument)
+}
+
 fn create_query_literal(text: String, modifiers: QueryModifiersTracking) -> QueryLiteral {
     if modifiers.regex {
         // Create RegexQuery
@@ -209,80 +264,111 @@ fn create_query_literal(text: String, modifiers: QueryModifiersTracking) -> Quer
             match_path: modifiers.match_path,
             whole_filename: modifiers.whole_filename,
             whole_word: modifiers.whole_word,
+            fuzzy: modifiers.fuzzy,
         })
     }
 }
 
 // Parses a function like size:>1000 or datecreated:<2023-01-01
-fn parse_function(lexer: &mut lexer::QueryLexer, name: &str) -> Option<QueryFunction> {
+fn parse_function(
+    lexer: &mut lexer::QueryLexer,
+    name: &str,
+    options: &QueryParserOptions,
+) -> Option<QueryFunction> {
     let name = name.to_lowercase();
     let name = name.as_str();
     match name {
         "size" => {
             let cmp = get_comparison(lexer)?;
-            if let Some(token) = lexer.next_token() {
-                match token {
-                    lexer::QueryToken::Ident(num_str) | lexer::QueryToken::StrLit(num_str) => {
-                        if let Ok(size) = num_str.parse::<u64>() {
-                            return Some(QueryFunction::Size(cmp, size));
-                        }
-                    }
-                    _ => {}
+            if let Some(lexer::QueryToken::Ident(num_str) | lexer::QueryToken::StrLit(num_str)) =
+                lexer.next_token()
+            {
+                if let Ok(size) = num_str.parse::<u64>() {
+                    return Some(QueryFunction::Size(cmp, size, size));
                 }
+                return SIZE_KEYWORDS
+                    .iter()
+                    .find(|(keyword, _, _)| keyword.eq_ignore_ascii_case(&num_str))
+                    .map(|&(_, start, end)| QueryFunction::Size(cmp, start, end));
             }
             None
         }
         "datemodified" | "dm" | "datecreated" | "dc" => {
-            let cmp = get_comparison(lexer)?;
-            if let Some(token) = lexer.next_token() {
-                match token {
-                    lexer::QueryToken::Ident(date_str) | lexer::QueryToken::StrLit(date_str) => {
-                        // use QueryDate::from
-                        let date = QueryDate::from(date_str.as_str());
-                        return Some(if name.starts_with("datecreated") || name == "dc" {
-                            QueryFunction::DateCreated(cmp, date)
-                        } else {
-                            QueryFunction::DateModified(cmp, date)
-                        });
-                    }
-                    _ => {}
-                }
+            let mut cmp = get_comparison(lexer)?;
+            let date_str = read_argument(lexer)?;
+            if date_str.contains("..") {
+                cmp = QueryCmp::Range;
             }
-            None
-        }
-        "parent" | "infolder" | "nosubfolders" => {
-            if let Some(token) = lexer.next_token() {
-                match token {
-                    lexer::QueryToken::Ident(folder) | lexer::QueryToken::StrLit(folder) => {
-                        return Some(QueryFunction::Parent(folder));
-                    }
-                    _ => {}
-                }
-            }
-            None
+            let date = QueryDate::parse(&date_str, &options.date);
+            Some(if name.starts_with("datecreated") || name == "dc" {
+                QueryFunction::DateCreated(cmp, date)
+            } else {
+                QueryFunction::DateModified(cmp, date)
+            })
         }
+        // Folder paths may contain colons like c:\windows
+        "parent" => read_argument(lexer).map(QueryFunction::Parent),
+        "infolder" => read_argument(lexer).map(QueryFunction::InFolder),
+        "nosubfolders" => read_argument(lexer).map(QueryFunction::NoSubfolders),
         "ext" => {
-            let mut exts = Vec::new();
-            while let Some(token) = lexer.next_token() {
-                match token {
-                    lexer::QueryToken::Ident(ext) | lexer::QueryToken::StrLit(ext) => {
-                        exts.push(ext);
-                    }
-                    _ => {}
-                }
-            }
+            // Semicolon separated list like ext:jpg;png
+            let exts: Vec<String> = read_argument(lexer)?
+                .split(';')
+                .map(|ext| ext.trim_start_matches('.').to_lowercase())
+                .filter(|ext| !ext.is_empty())
+                .collect();
             if !exts.is_empty() {
                 return Some(QueryFunction::Ext(exts));
             }
             None
         }
-        _ => None,
+        "startwith" => read_argument(lexer).map(QueryFunction::StartWith),
+        // Take a condition like child:package.json or descendant:< ext:psd | ext:ai >
+        "child" => Some(QueryFunction::Child(Box::new(parse_condition(
+            lexer,
+            QueryModifiersTracking::default(),
+            options,
+        )))),
+        "descendant" => Some(QueryFunction::Descendant(Box::new(parse_condition(
+            lexer,
+            QueryModifiersTracking::default(),
+            options,
+        )))),
+        // Content type macros like audio: or pic:, which take no argument
+        _ => FileCategory::from_macro(name).map(QueryFunction::Category),
     }
 }
 
+// Expands a user-defined macro like src: or proj:foo into the expression it stands for
+fn expand_macro(
+    lexer: &mut lexer::QueryLexer,
+    name: &str,
+    modifiers: QueryModifiersTracking,
+    options: &QueryParserOptions,
+) -> Option<QueryExpr> {
+    let position = options
+        .macros
+        .iter()
+        .position(|query_macro| query_macro.name.eq_ignore_ascii_case(name))?;
+    let query_macro = &options.macros[position];
+    let argument = match lexer.peek_token() {
+        Some(lexer::QueryToken::Ident(_) | lexer::QueryToken::StrLit(_)) if query_macro.takes_argument() => {
+            read_argument(lexer)
+        }
+        _ => None,
+    };
+    let expansion = query_macro.expand(argument.as_deref());
+
+    // Parse the expansion without the macro itself, so recursive definitions terminate
+    let mut inner_options = options.clone();
+    inner_options.macros.remove(position);
+    let mut macro_lexer = lexer::QueryLexer::new(&expansion);
+    Some(parse_expression(&mut macro_lexer, modifiers, &inner_options))
+}
+
 // Parses a modifier like case:query or file:query
 fn parse_modifier(
-    ident: &String,
+    ident: &str,
     modifiers: QueryModifiersTracking,
 ) -> Option<QueryModifiersTracking> {
     let mut modifiers = modifiers;
@@ -313,6 +399,8 @@ fn parse_modifier(
         "nowholeword" | "noww" => modifiers.whole_word = false,
         "wildcards" => modifiers.wildcards = true,
         "nowildcards" => modifiers.wildcards = false,
+        "fuzzy" => modifiers.fuzzy = true,
+        "nofuzzy" => modifiers.fuzzy = false,
         _ => {
             return None; // Not a modifier
         }
@@ -324,43 +412,55 @@ fn parse_modifier(
 // Parses a single condition, which could be a function, a text query, or a negation
 // e.g. size:>1000, "example.txt", file:case:"ExAmplE.txt", !ext:tmp
 // extreme cases: !case:!file:"!"tmp  // double negation with query !tmp
-fn parse_condition(lexer: &mut lexer::QueryLexer, modifiers: QueryModifiersTracking) -> QueryExpr {
+fn parse_condition(
+    lexer: &mut lexer::QueryLexer,
+    modifiers: QueryModifiersTracking,
+    options: &QueryParserOptions,
+) -> QueryExpr {
     if let Some(token) = lexer.next_token() {
+        let mut search_text = token.to_string();
         match token {
             lexer::QueryToken::Ident(ref ident) => {
                 // Check if next token is Colon for function
                 if let Some(lexer::QueryToken::Colon) = lexer.peek_token() {
                     // Consume Colon
                     lexer.next_token();
+                    search_text.push(':');
                     // Try parse function
-                    if let Some(func) = parse_function(lexer, &ident) {
+                    if let Some(func) = parse_function(lexer, ident, options) {
                         return QueryExpr::Function(func);
                     } else if let Some(new_modifiers) =
-                        parse_modifier(&ident, modifiers)
+                        parse_modifier(ident, modifiers)
                     {
                         // If it's a modifier, update modifiers and continue
-                        return parse_condition(lexer, new_modifiers);
+                        return parse_condition(lexer, new_modifiers, options);
+                    } else if let Some(expr) = expand_macro(lexer, ident, modifiers, options) {
+                        return expr;
                     }
                 }
                 // Otherwise, treat as text query
             }
             lexer::QueryToken::Not => {
-                let sub_expr = parse_condition(lexer, modifiers);
+                let sub_expr = parse_condition(lexer, modifiers, options);
                 return QueryExpr::Not(Box::new(sub_expr));
             }
             lexer::QueryToken::Whitespace => {
-                unreachable!("Whitespace should be handled in parse_expression");
+                // Modifier without text like "file: ", matches everything the modifiers allow
+                return QueryExpr::Literal(create_query_literal(String::new(), modifiers));
             }
             lexer::QueryToken::LessThan => {
-                // start of block
-                return parse_expression(lexer, modifiers);
+                // start of block, ends with ">"
+                let expr = parse_expression(lexer, modifiers, options);
+                if let Some(lexer::QueryToken::GreaterThan) = lexer.peek_token() {
+                    lexer.next_token();
+                }
+                return expr;
             }
             _ => {
                 // Otherwise, treat as text query
             }
         };
 
-        let mut search_text = token.to_string();
         while let Some(next_token) = lexer.peek_token() {
             match next_token {
                 lexer::QueryToken::Whitespace | lexer::QueryToken::Or => break,
@@ -375,20 +475,15 @@ fn parse_condition(lexer: &mut lexer::QueryLexer, modifiers: QueryModifiersTrack
         let literal = create_query_literal(search_text, modifiers);
         return QueryExpr::Literal(literal);
     }
-    // Default to empty text query if nothing matched
-    QueryExpr::Literal(QueryLiteral::Text(TextQuery {
-        text: "".to_string(),
-        case_sensitive: false,
-        diacritics_sensitive: false,
-        file_only: false,
-        folder_only: false,
-        match_path: false,
-        whole_filename: false,
-        whole_word: false,
-    }))
+    // Default to empty text query if nothing matched, e.g. "file:" at the end of the input
+    QueryExpr::Literal(create_query_literal(String::new(), modifiers))
 }
 
-fn parse_expression(lexer: &mut lexer::QueryLexer, modifiers: QueryModifiersTracking) -> QueryExpr {
+fn parse_expression(
+    lexer: &mut lexer::QueryLexer,
+    modifiers: QueryModifiersTracking,
+    options: &QueryParserOptions,
+) -> QueryExpr {
     let mut exprs = Vec::new();
     while let Some(token) = lexer.peek_token() {
         match token {
@@ -396,15 +491,19 @@ fn parse_expression(lexer: &mut lexer::QueryLexer, modifiers: QueryModifiersTrac
                 // Just skip whitespace
                 lexer.next_token();
             }
+            lexer::QueryToken::GreaterThan => {
+                // End of block
+                break;
+            }
             lexer::QueryToken::Or => {
                 // Parse next condition and combine with Or
                 lexer.next_token(); // consume Or
-                let right_expr = parse_expression(lexer, modifiers);
+                let right_expr = parse_expression(lexer, modifiers, options);
                 let left_expr = exprs_to_and(exprs);
                 return QueryExpr::Or(Box::new(left_expr), Box::new(right_expr));
             }
             _ => {
-                exprs.push(parse_condition(lexer, modifiers));
+                exprs.push(parse_condition(lexer, modifiers, options));
             }
         }
     }
@@ -412,6 +511,10 @@ fn parse_expression(lexer: &mut lexer::QueryLexer, modifiers: QueryModifiersTrac
 }
 
 pub fn parse_query(input: &str) -> QueryExpr {
+    parse_query_with_options(input, &QueryParserOptions::default())
+}
+
+pub fn parse_query_with_options(input: &str, options: &QueryParserOptions) -> QueryExpr {
     let trimmed = input.trim();
     if trimmed.is_empty() {
         // Return a default empty query or handle as needed
@@ -424,10 +527,35 @@ pub fn parse_query(input: &str) -> QueryExpr {
             match_path: false,
             whole_filename: false,
             whole_word: false,
+            fuzzy: false,
         }));
     }
 
     let mut lexer = lexer::QueryLexer::new(input);
     let modifiers = QueryModifiersTracking::default();
-    parse_expression(&mut lexer, modifiers)
+    let mut exprs = Vec::new();
+    loop {
+        exprs.push(parse_expression(&mut lexer, modifiers, options));
+        // parse_expression stops at a ">" without matching "<", skip it and continue
+        if lexer.next_token().is_none() {
+            break;
+        }
+    }
+    exprs_to_and(exprs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_terms() {
+        let query = parse_query("fuzzy:\"receive invoice\" !draft | ext:pdf file: < report size:>10 >");
+        let terms: Vec<(&str, bool)> = query
+            .text_terms()
+            .iter()
+            .map(|term| (term.text.as_str(), term.fuzzy))
+            .collect();
+        assert_eq!(terms, vec![("receive invoice", true), ("report", false)]);
+    }
 }
@@ -1,7 +1,19 @@
+use std::error::Error;
+use std::sync::OnceLock;
+
 use crate::{
-    file_tree::{self, FileTree},
-    indexer::bigram_index::BigramIndex,
-    post_filter,
+    file_tree::{self, ElementId, FileTree},
+    indexer::{
+        bigram_index::{BigramIndex, default_threads},
+        prefix_index::PrefixIndex,
+        text_index::{NgramIndex, TextIndex},
+    },
+    memory::MemoryUsage,
+    query::{
+        highlighter::{Highlights, highlight},
+        query_evaluator::QueryEvaluator,
+        query_parser::{QueryExpr, QueryFunction, QueryParserOptions, parse_query_with_options},
+    },
     sorter::{SortField, SortOrder, Sorter},
 };
 
@@ -9,16 +21,115 @@ pub struct Searcher {
     pub file_tree: FileTree,
     pub bigram_index: BigramIndex,
     pub sorter: Sorter,
+    pub query_options: QueryParserOptions,
+    // Built on first use by startwith: or typeahead
+    pub prefix_index: OnceLock<PrefixIndex>,
+    // Optional index of longer grams for more selective lookups, see with_ngram_index
+    pub ngram_index: Option<NgramIndex>,
 }
 
 impl Searcher {
     pub fn from_file_tree(tree: FileTree) -> Self {
-        let bigram_index = BigramIndex::new(&tree);
-        let sorter = Sorter::new();
+        Searcher::from_file_tree_with_threads(tree, default_threads())
+    }
+    // Builds the bigram index with the given number of threads, see BigramIndex::with_threads
+    pub fn from_file_tree_with_threads(tree: FileTree, threads: usize) -> Self {
+        let bigram_index = BigramIndex::with_threads(&tree, threads);
+        Searcher::from_parts(tree, bigram_index, Sorter::new())
+    }
+
+    // Combines an already built tree, index and sorter, e.g. loaded from a snapshot
+    pub fn from_parts(mut tree: FileTree, bigram_index: BigramIndex, sorter: Sorter) -> Self {
+        if !tree.has_intervals() {
+            tree.compute_intervals();
+        }
         Searcher {
             file_tree: tree,
             bigram_index,
             sorter,
+            query_options: QueryParserOptions::default(),
+            prefix_index: OnceLock::new(),
+            ngram_index: None,
+        }
+    }
+
+    // Builds an index of n-grams, used for texts of at least n characters
+    pub fn with_ngram_index(mut self, n: usize) -> Self {
+        self.ngram_index = Some(NgramIndex::new(&self.file_tree, n));
+        self
+    }
+
+    // Adds or updates an element after the searcher was built. The indexes collect new elements
+    // in delta segments and the sorter the changed elements, see merge_updates.
+    pub fn add_or_update(
+        &mut self,
+        path: &str,
+        size: Option<i64>,
+        date_modified: Option<i64>,
+        date_created: Option<i64>,
+        attributes: u32,
+    ) -> ElementId {
+        let len_before = self.file_tree.len();
+        let index = self
+            .file_tree
+            .add_or_update_recursive(path, size, date_modified, date_created, attributes);
+        // Missing parent folders are created along with the element
+        let mut changed: Vec<ElementId> = (len_before..self.file_tree.len()).map(ElementId::new).collect();
+        if index.index() < len_before {
+            changed.push(index);
+        }
+        if self.file_tree.len() > len_before {
+            self.bigram_index.add_elements(&self.file_tree);
+            if let Some(ngram_index) = &mut self.ngram_index {
+                ngram_index.add_elements(&self.file_tree);
+            }
+            // The prefix index is rebuilt on next use
+            self.prefix_index = OnceLock::new();
+        }
+        self.sorter.update(&changed);
+        index
+    }
+
+    // Removes the element and everything below it. The tombstones stay in the indexes but never
+    // show up in results.
+    pub fn remove(&mut self, index: ElementId) -> Result<Vec<ElementId>, Box<dyn Error>> {
+        let removed = self.file_tree.remove_recursive(index)?;
+        self.prefix_index = OnceLock::new();
+        Ok(removed)
+    }
+
+    // Renames the element and indexes its new name, returns the elements whose paths changed
+    pub fn rename(&mut self, index: ElementId, new_name: &str) -> Result<Vec<ElementId>, Box<dyn Error>> {
+        let affected = self.file_tree.rename(index, new_name)?;
+        self.bigram_index.update_elements(&self.file_tree, &[index]);
+        if let Some(ngram_index) = &mut self.ngram_index {
+            ngram_index.update_elements(&self.file_tree, &[index]);
+        }
+        self.sorter.update(&[index]);
+        self.prefix_index = OnceLock::new();
+        Ok(affected)
+    }
+
+    // Moves the element into another folder, returns the elements whose paths changed.
+    // Names stay the same, so only the pre-order numbering has to be recomputed, see merge_updates.
+    pub fn move_to(
+        &mut self,
+        index: ElementId,
+        new_parent: ElementId,
+    ) -> Result<Vec<ElementId>, Box<dyn Error>> {
+        self.file_tree.move_to(index, new_parent)
+    }
+
+    // Merges the delta segments of the indexes and renumbers the tree, best after a batch of updates.
+    // Searching works without it, just slower.
+    pub fn merge_updates(&mut self) {
+        self.bigram_index.merge_delta();
+        if let Some(ngram_index) = &mut self.ngram_index {
+            ngram_index.merge_delta();
+        }
+        self.sorter.apply_changes(&self.file_tree);
+        if !self.file_tree.has_intervals() {
+            self.file_tree.compute_intervals();
         }
     }
 
@@ -27,47 +138,206 @@ impl Searcher {
         query: T,
         sort_by: Option<SortField>,
         sort_order: Option<SortOrder>,
-    ) -> Vec<usize> {
-        let mut indices: Vec<usize>;
+    ) -> Vec<ElementId> {
+        self.search_top(query, sort_by, sort_order, usize::MAX)
+    }
 
-        // Normalize the query to lowercase for case-insensitive search
-        let query = query.as_ref().to_lowercase();
-        let query_len = query.chars().count();
+    // Like search, but when ranking by relevance only the first limit results are in order.
+    // Meant for showing the first pages of a query with many results.
+    pub fn search_top<T: AsRef<str>>(
+        &self,
+        query: T,
+        sort_by: Option<SortField>,
+        sort_order: Option<SortOrder>,
+        limit: usize,
+    ) -> Vec<ElementId> {
+        let query = query.as_ref();
 
         // Search
-        if query.is_empty() {
-            // query is empty, return all indices
-            indices = (0..self.file_tree.len()).collect::<Vec<usize>>();
-        } else if query_len < 2 {
-            // query is 1 character
-            indices = self.bigram_index.query_char(query.chars().next().unwrap());
-        } else {
-            // query is longer than 1 character
-            indices = self.bigram_index.query_word(&query);
-            if query_len > 2 {
-                // If the query is longer than 2 characters, apply post-filtering
-                post_filter::post_filter(&self.file_tree, &mut indices, &query);
-            }
+        let parsed_query = parse_query_with_options(query, &self.query_options);
+        let mut evaluator = QueryEvaluator::new(&self.file_tree, &self.bigram_index);
+        if let Some(ngram_index) = &self.ngram_index {
+            evaluator = evaluator.with_ngram_index(ngram_index);
         }
+        if uses_startwith(&parsed_query) {
+            evaluator = evaluator.with_prefix_index(self.prefix_index());
+        }
+        let mut indices = evaluator.evaluate(&parsed_query);
 
         println!(
             "Found {} matching records for query '{}'",
             indices.len(),
             query
         );
-        // Sort results if a sort field is provided
-        if let Some(sort_by) = sort_by {
-            let sort_order = sort_order.unwrap_or(SortOrder::Ascending);
-            self.sorter
-                .sort_by(&self.file_tree, indices.as_mut_slice(), sort_by, sort_order);
+        // Sort results if a sort field is provided, otherwise rank them by relevance
+        let sort_by = sort_by.or((!query.trim().is_empty()).then_some(SortField::Relevance));
+        match sort_by {
+            Some(SortField::Relevance) => {
+                let sort_order = sort_order.unwrap_or(SortOrder::Descending);
+                self.sorter.sort_top_by_relevance(
+                    &self.file_tree,
+                    indices.as_mut_slice(),
+                    &parsed_query.text_terms(),
+                    sort_order,
+                    limit,
+                );
+            }
+            Some(sort_by) => {
+                let sort_order = sort_order.unwrap_or(SortOrder::Ascending);
+                self.sorter
+                    .sort_by(&self.file_tree, indices.as_mut_slice(), sort_by, sort_order);
+            }
+            None => {}
         }
         indices
     }
 
+    pub fn prefix_index(&self) -> &PrefixIndex {
+        self.prefix_index
+            .get_or_init(|| PrefixIndex::new(&self.file_tree, &self.sorter))
+    }
+
+    // The first n distinct filenames starting with the prefix, for suggestions while typing
+    pub fn typeahead<T: AsRef<str>>(&self, prefix: T, n: usize) -> Vec<&str> {
+        self.prefix_index()
+            .top_filenames(&self.file_tree, prefix.as_ref(), n)
+    }
+
+    // What matched the query in each of the given results, meant for the page being displayed
+    pub fn highlights<T: AsRef<str>>(&self, query: T, indices: &[ElementId]) -> Vec<Highlights> {
+        let parsed_query = parse_query_with_options(query.as_ref(), &self.query_options);
+        indices
+            .iter()
+            .map(|&index| highlight(&parsed_query, &self.file_tree, index))
+            .collect()
+    }
+
+    // Breakdown of the memory used by the tree, the indexes and the cached sort orders
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let mut usage = MemoryUsage::new("searcher")
+            .with_part(self.file_tree.memory_usage())
+            .with_part(self.bigram_index.memory_usage())
+            .with_part(self.sorter.memory_usage());
+        if let Some(prefix_index) = self.prefix_index.get() {
+            usage = usage.with("prefix_index", prefix_index.memory_usage());
+        }
+        if let Some(ngram_index) = &self.ngram_index {
+            usage = usage.with("ngram_index", ngram_index.memory_usage());
+        }
+        usage
+    }
+
     pub fn get_file_tree(&self) -> &FileTree {
         &self.file_tree
     }
-    pub fn get(&self, index: usize) -> Option<&file_tree::Element> {
+    pub fn get(&self, index: ElementId) -> Option<file_tree::ElementRef<'_>> {
         self.file_tree.get(index)
     }
 }
+
+fn uses_startwith(expr: &QueryExpr) -> bool {
+    match expr {
+        QueryExpr::Function(QueryFunction::StartWith(_)) => true,
+        QueryExpr::Function(QueryFunction::Child(inner) | QueryFunction::Descendant(inner)) => {
+            uses_startwith(inner)
+        }
+        QueryExpr::And(left, right) | QueryExpr::Or(left, right) => {
+            uses_startwith(left) || uses_startwith(right)
+        }
+        QueryExpr::Not(inner) => uses_startwith(inner),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_or_update() {
+        let mut tree = FileTree::with_capacity(10);
+        tree.add_or_update_recursive("docs\\report.pdf", Some(300), None, None, 0);
+        tree.add_or_update_recursive("docs\\notes.txt", Some(100), None, None, 0);
+        let mut searcher = Searcher::from_file_tree(tree).with_ngram_index(3);
+        let sorted_by_size = |searcher: &Searcher| searcher.search("", Some(SortField::Size), None);
+        sorted_by_size(&searcher);
+        assert_eq!(searcher.typeahead("re", 10), vec!["report.pdf"]);
+
+        let draft = searcher.add_or_update("drafts\\report_v2.pdf", Some(200), None, None, 0);
+        let notes = searcher.add_or_update("docs\\notes.txt", Some(500), None, None, 0);
+        assert!(searcher.bigram_index.has_delta());
+
+        // New elements are found before and after merging the delta segments
+        for merged in [false, true] {
+            if merged {
+                searcher.merge_updates();
+                assert!(!searcher.bigram_index.has_delta());
+                assert!(searcher.file_tree.has_intervals());
+            }
+            let report = searcher.search("report", Some(SortField::Filename), None);
+            assert_eq!(report.len(), 2);
+            assert!(report.contains(&draft));
+            assert_eq!(searcher.search("v2", None, None), vec![draft]);
+            assert_eq!(searcher.search("port_v", None, None), vec![draft]);
+            assert_eq!(searcher.search("infolder:drafts", None, None), vec![draft]);
+            assert_eq!(searcher.typeahead("re", 10), vec!["report.pdf", "report_v2.pdf"]);
+            // The changed size moves the notes to the end
+            assert_eq!(sorted_by_size(&searcher).last(), Some(&notes));
+        }
+    }
+
+    #[test]
+    fn test_memory_usage() {
+        let mut tree = FileTree::with_capacity(10);
+        tree.add_or_update_recursive("docs\\report.pdf", Some(300), None, None, 0);
+        let searcher = Searcher::from_file_tree(tree);
+        let usage = searcher.memory_usage();
+        let part = |name| usage.get(name).unwrap().bytes;
+        assert_eq!(usage.bytes, part("file_tree") + part("bigram_index") + part("sorter"));
+        assert!(part("strbuf") >= "Rootdocsreport.pdf".len());
+        assert!(part("bigram_postings") > 0);
+        // Sort orders and the prefix index only count once they are built
+        assert_eq!(part("sorter"), 0);
+        assert!(usage.get("prefix_index").is_none());
+        searcher.search("", Some(SortField::Size), None);
+        searcher.typeahead("re", 10);
+        let usage = searcher.memory_usage();
+        assert!(usage.get("size_order").unwrap().bytes >= 3 * std::mem::size_of::<ElementId>());
+        assert!(usage.get("prefix_index").unwrap().bytes > 0);
+    }
+
+    #[test]
+    fn test_remove_and_rename() {
+        let mut tree = FileTree::with_capacity(10);
+        let report = tree.add_or_update_recursive("docs\\report.pdf", Some(300), None, None, 0);
+        let notes = tree.add_or_update_recursive("docs\\notes.txt", Some(100), None, None, 0);
+        let song = tree.add_or_update_recursive("music\\song.mp3", Some(200), None, None, 0);
+        let music = parent_of(&tree, song);
+        let mut searcher = Searcher::from_file_tree(tree).with_ngram_index(3);
+        searcher.search("", Some(SortField::Filename), None);
+
+        assert_eq!(searcher.rename(notes, "minutes.txt").unwrap(), vec![notes]);
+        assert_eq!(searcher.search("minutes", None, None), vec![notes]);
+        assert_eq!(searcher.search("minu", None, None), vec![notes]);
+        assert_eq!(searcher.search("notes", None, None), Vec::new());
+        let files = searcher.search("file:", Some(SortField::Filename), None);
+        assert_eq!(files, vec![notes, report, song]);
+
+        assert_eq!(searcher.move_to(report, music).unwrap(), vec![report]);
+        assert_eq!(searcher.search("infolder:music", None, None), vec![report, song]);
+
+        let mut removed = searcher.remove(music).unwrap();
+        removed.sort_unstable();
+        assert_eq!(removed, vec![report, music, song]);
+        let docs = parent_of(&searcher.file_tree, notes);
+        assert_eq!(searcher.search("", None, None), vec![docs, notes]);
+        assert_eq!(searcher.search("song", None, None), Vec::new());
+        assert_eq!(searcher.typeahead("so", 10), Vec::<&str>::new());
+        searcher.merge_updates();
+        assert_eq!(searcher.search("!minutes", None, None).len(), 1);
+    }
+
+    fn parent_of(tree: &FileTree, index: ElementId) -> ElementId {
+        tree.parent(index)
+    }
+}
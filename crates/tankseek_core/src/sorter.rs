@@ -1,4 +1,8 @@
-use crate::file_tree::FileTree;
+use crate::file_tree::{ElementId, FileTree, RawId};
+use crate::memory::{MemoryUsage, vec_bytes};
+use crate::query::fuzzy::fuzzy_distance;
+use crate::query::query_evaluator::filetime_to_unix;
+use crate::query::query_parser::TextQuery;
 use std::sync::Mutex;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -7,6 +11,8 @@ pub enum SortField {
     DateModified,
     DateCreated,
     Size,
+    // How well the result matches the query, needs the query terms, see sort_by_relevance
+    Relevance,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -14,11 +20,15 @@ pub enum SortOrder {
     Ascending,
     Descending,
 }
+// The cached orders hold the rank of every element
+#[derive(Default)]
 pub struct Sorter {
-    pub filename_order: Mutex<Option<Vec<usize>>>,
-    pub date_modified_order: Mutex<Option<Vec<usize>>>,
-    pub date_created_order: Mutex<Option<Vec<usize>>>,
-    pub size_order: Mutex<Option<Vec<usize>>>,
+    pub filename_order: Mutex<Option<Vec<RawId>>>,
+    pub date_modified_order: Mutex<Option<Vec<RawId>>>,
+    pub date_created_order: Mutex<Option<Vec<RawId>>>,
+    pub size_order: Mutex<Option<Vec<RawId>>>,
+    // Elements changed since the orders were last patched, see update
+    pub changed: Mutex<Vec<ElementId>>,
 }
 impl Sorter {
     pub fn new() -> Self {
@@ -27,16 +37,18 @@ impl Sorter {
             date_modified_order: Mutex::new(None),
             date_created_order: Mutex::new(None),
             size_order: Mutex::new(None),
+            changed: Mutex::new(Vec::new()),
         }
     }
 
     pub fn sort_by(
         &self,
         tree: &FileTree,
-        elements: &mut [usize],
+        elements: &mut [ElementId],
         field: SortField,
         order: SortOrder,
     ) {
+        self.apply_changes(tree);
         match field {
             SortField::Filename => {
                 self.prepare_filename_order(tree);
@@ -58,19 +70,102 @@ impl Sorter {
                 let size_order = self.size_order.lock().unwrap();
                 self.sort_by_order_list(elements, size_order.as_ref().unwrap(), order);
             }
+            SortField::Relevance => self.sort_by_relevance(tree, elements, &[], order),
         }
     }
+
+    // Sorts by relevance to the text terms of the query. Descending puts the best matches first.
+    pub fn sort_by_relevance(
+        &self,
+        tree: &FileTree,
+        elements: &mut [ElementId],
+        terms: &[&TextQuery],
+        order: SortOrder,
+    ) {
+        self.sort_top_by_relevance(tree, elements, terms, order, elements.len());
+    }
+
+    // Like sort_by_relevance, but only the first limit elements end up sorted, the rest follow in
+    // no particular order. Cheaper when only the first pages of many results are shown.
+    pub fn sort_top_by_relevance(
+        &self,
+        tree: &FileTree,
+        elements: &mut [ElementId],
+        terms: &[&TextQuery],
+        order: SortOrder,
+        limit: usize,
+    ) {
+        let mut scorer = RelevanceScorer::new(terms);
+        // Every element is scored once. The position breaks ties, so equally relevant results keep
+        // their index order.
+        let mut keyed: Vec<(i64, usize, ElementId)> = elements
+            .iter()
+            .enumerate()
+            .map(|(position, &index)| {
+                let score = scorer.score(tree, index);
+                let key = match order {
+                    SortOrder::Ascending => score,
+                    SortOrder::Descending => -score,
+                };
+                (key, position, index)
+            })
+            .collect();
+        let limit = limit.min(keyed.len());
+        if limit < keyed.len() {
+            keyed.select_nth_unstable(limit);
+        }
+        keyed[..limit].sort_unstable();
+        for (element, (_, _, index)) in elements.iter_mut().zip(keyed) {
+            *element = index;
+        }
+    }
+    // Computes all cached orders up front, e.g. before writing a snapshot
+    pub fn prepare_all(&self, tree: &FileTree) {
+        self.apply_changes(tree);
+        self.prepare_filename_order(tree);
+        self.prepare_date_modified_order(tree);
+        self.prepare_date_created_order(tree);
+        self.prepare_size_order(tree);
+    }
+
+    // Notes elements that were added or whose metadata changed. The cached orders are patched in
+    // one batch when they are used next, so a series of updates doesn't redo the orders each time.
+    pub fn update(&self, changed: &[ElementId]) {
+        self.changed.lock().unwrap().extend_from_slice(changed);
+    }
+
+    // Patches the cached orders with the elements changed since the last call. The changed
+    // elements are sorted on their own and merged into the existing order, which is linear
+    // instead of sorting all elements again.
+    pub fn apply_changes(&self, tree: &FileTree) {
+        let changed = std::mem::take(&mut *self.changed.lock().unwrap());
+        if changed.is_empty() {
+            return;
+        }
+        let orders = [
+            (SortField::Filename, &self.filename_order),
+            (SortField::DateModified, &self.date_modified_order),
+            (SortField::DateCreated, &self.date_created_order),
+            (SortField::Size, &self.size_order),
+        ];
+        for (field, order) in orders {
+            if let Some(order) = order.lock().unwrap().as_mut() {
+                patch_order(tree, order, &changed, field);
+            }
+        }
+    }
+
     fn prepare_filename_order(&self, tree: &FileTree) {
         let mut filename_order = self.filename_order.lock().unwrap();
         if filename_order.is_none() {
             println!("Preparing filename order...");
             let timestamp = std::time::Instant::now();
-            let mut sorted: Vec<usize> = (0..tree.get_elements().len()).collect();
-            sorted.sort_unstable_by(|&a, &b| tree.get_filename(a).cmp(&tree.get_filename(b)));
-            let mut order = vec![0; sorted.len()];
+            let mut sorted: Vec<ElementId> = tree.ids().collect();
+            sorted.sort_unstable_by(|&a, &b| cmp_filenames(tree.get_filename(a), tree.get_filename(b)));
+            let mut order: Vec<RawId> = vec![0; sorted.len()];
 
             for (i, &index) in sorted.iter().enumerate() {
-                order[index] = i;
+                order[index.index()] = i as RawId;
             }
 
             println!(
@@ -82,22 +177,41 @@ impl Sorter {
         }
     }
 
+    // Memory of the cached orders, orders that were never needed take none
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let bytes = |order: &Mutex<Option<Vec<RawId>>>| order.lock().unwrap().as_ref().map_or(0, vec_bytes);
+        MemoryUsage::new("sorter")
+            .with("filename_order", bytes(&self.filename_order))
+            .with("date_modified_order", bytes(&self.date_modified_order))
+            .with("date_created_order", bytes(&self.date_created_order))
+            .with("size_order", bytes(&self.size_order))
+            .with("changed", vec_bytes(&self.changed.lock().unwrap()))
+    }
+
+    // Element indices sorted by filename, the inverse of the filename order
+    pub fn filename_permutation(&self, tree: &FileTree) -> Vec<ElementId> {
+        self.apply_changes(tree);
+        self.prepare_filename_order(tree);
+        let filename_order = self.filename_order.lock().unwrap();
+        let filename_order = filename_order.as_ref().unwrap();
+        let mut sorted = vec![ElementId::ROOT; filename_order.len()];
+        for (index, &rank) in filename_order.iter().enumerate() {
+            sorted[rank as usize] = ElementId::new(index);
+        }
+        sorted
+    }
+
     fn prepare_date_modified_order(&self, tree: &FileTree) {
         let mut date_modified_order = self.date_modified_order.lock().unwrap();
         if date_modified_order.is_none() {
             println!("Preparing date modified order...");
             let timestamp = std::time::Instant::now();
-            let mut sorted: Vec<usize> = (0..tree.get_elements().len()).collect();
-            sorted.sort_unstable_by(|&a, &b| {
-                tree.get(a)
-                    .unwrap()
-                    .date_modified
-                    .cmp(&tree.get(b).unwrap().date_modified)
-            });
-            let mut order = vec![0; sorted.len()];
+            let mut sorted: Vec<ElementId> = tree.ids().collect();
+            sorted.sort_unstable_by_key(|&index| tree.date_modified(index));
+            let mut order: Vec<RawId> = vec![0; sorted.len()];
 
             for (i, &index) in sorted.iter().enumerate() {
-                order[index] = i;
+                order[index.index()] = i as RawId;
             }
 
             println!(
@@ -114,17 +228,12 @@ impl Sorter {
         if date_created_order.is_none() {
             println!("Preparing date created order...");
             let timestamp = std::time::Instant::now();
-            let mut sorted: Vec<usize> = (0..tree.get_elements().len()).collect();
-            sorted.sort_unstable_by(|&a, &b| {
-                tree.get(a)
-                    .unwrap()
-                    .date_created
-                    .cmp(&tree.get(b).unwrap().date_created)
-            });
-            let mut order = vec![0; sorted.len()];
+            let mut sorted: Vec<ElementId> = tree.ids().collect();
+            sorted.sort_unstable_by_key(|&index| tree.date_created(index));
+            let mut order: Vec<RawId> = vec![0; sorted.len()];
 
             for (i, &index) in sorted.iter().enumerate() {
-                order[index] = i;
+                order[index.index()] = i as RawId;
             }
 
             println!(
@@ -141,14 +250,12 @@ impl Sorter {
         if size_order.is_none() {
             println!("Preparing size order...");
             let timestamp = std::time::Instant::now();
-            let mut sorted: Vec<usize> = (0..tree.get_elements().len()).collect();
-            sorted.sort_unstable_by(|&a, &b| {
-                tree.get(a).unwrap().size.cmp(&tree.get(b).unwrap().size)
-            });
-            let mut order = vec![0; sorted.len()];
+            let mut sorted: Vec<ElementId> = tree.ids().collect();
+            sorted.sort_unstable_by_key(|&index| tree.size(index));
+            let mut order: Vec<RawId> = vec![0; sorted.len()];
 
             for (i, &index) in sorted.iter().enumerate() {
-                order[index] = i;
+                order[index.index()] = i as RawId;
             }
 
             println!(
@@ -162,30 +269,166 @@ impl Sorter {
 
     fn sort_by_order_list(
         &self,
-        elements: &mut [usize],
-        order_list: &Vec<usize>,
+        elements: &mut [ElementId],
+        order_list: &[RawId],
         order: SortOrder,
     ) {
         let len = order_list.len();
-        let mut elements_sorted: Vec<usize> = vec![usize::MAX; len];
+        let mut elements_sorted: Vec<Option<ElementId>> = vec![None; len];
         if order == SortOrder::Ascending {
-            for &index in elements.as_ref() {
-                elements_sorted[order_list[index]] = index;
+            for &index in elements.iter() {
+                elements_sorted[order_list[index.index()] as usize] = Some(index);
             }
         } else {
-            for &index in elements.as_ref() {
+            for &index in elements.iter() {
                 // For descending order, we need to reverse the order
-                elements_sorted[len - 1 - order_list[index]] = index;
+                elements_sorted[len - 1 - order_list[index.index()] as usize] = Some(index);
             }
         }
-        let mut counter = 0;
-        for i in 0..elements_sorted.len() {
-            if elements_sorted[i] != usize::MAX {
-                elements[counter] = elements_sorted[i];
-                counter += 1;
-            }
+        for (counter, index) in elements_sorted.into_iter().flatten().enumerate() {
+            elements[counter] = index;
+        }
+    }
+}
+
+// Moves the changed elements to their new position in the order and appends the new elements
+fn patch_order(tree: &FileTree, order: &mut Vec<RawId>, changed: &[ElementId], field: SortField) {
+    let cmp = |a: ElementId, b: ElementId| cmp_elements(tree, a, b, field);
+    let mut is_changed = vec![false; tree.len()];
+    for &index in changed {
+        is_changed[index.index()] = true;
+    }
+    // Elements beyond the old order are new
+    is_changed[order.len()..].fill(true);
+    let mut sorted = vec![ElementId::ROOT; order.len()];
+    for (index, &rank) in order.iter().enumerate() {
+        sorted[rank as usize] = ElementId::new(index);
+    }
+    sorted.retain(|&index| !is_changed[index.index()]);
+    let mut inserted: Vec<ElementId> = tree.ids().filter(|&index| is_changed[index.index()]).collect();
+    inserted.sort_unstable_by(|&a, &b| cmp(a, b));
+
+    // Merge both sorted lists
+    let mut merged = Vec::with_capacity(tree.len());
+    let (mut i, mut j) = (0, 0);
+    while i < sorted.len() && j < inserted.len() {
+        if cmp(inserted[j], sorted[i]) == std::cmp::Ordering::Less {
+            merged.push(inserted[j]);
+            j += 1;
+        } else {
+            merged.push(sorted[i]);
+            i += 1;
+        }
+    }
+    merged.extend_from_slice(&sorted[i..]);
+    merged.extend_from_slice(&inserted[j..]);
+
+    order.resize(tree.len(), 0);
+    for (rank, &index) in merged.iter().enumerate() {
+        order[index.index()] = rank as RawId;
+    }
+}
+
+// Compares two elements by one of the cached sort fields
+fn cmp_elements(tree: &FileTree, a: ElementId, b: ElementId, field: SortField) -> std::cmp::Ordering {
+    match field {
+        SortField::Filename => cmp_filenames(tree.get_filename(a), tree.get_filename(b)),
+        SortField::DateModified => tree.date_modified(a).cmp(&tree.date_modified(b)),
+        SortField::DateCreated => tree.date_created(a).cmp(&tree.date_created(b)),
+        SortField::Size => tree.size(a).cmp(&tree.size(b)),
+        SortField::Relevance => std::cmp::Ordering::Equal,
+    }
+}
+
+// Orders filenames ignoring case, names differing only in case are ordered by their bytes
+pub fn cmp_filenames(a: &str, b: &str) -> std::cmp::Ordering {
+    a.chars()
+        .flat_map(char::to_lowercase)
+        .cmp(b.chars().flat_map(char::to_lowercase))
+        .then_with(|| a.cmp(b))
+}
+
+// Scores how well elements match the query terms, higher is better. The terms are lowercased
+// once and the lowercased filename reuses one buffer, so scoring an element doesn't allocate.
+struct RelevanceScorer<'a> {
+    terms: Vec<(&'a TextQuery, String)>,
+    now: i64,
+    lower_filename: String,
+}
+impl<'a> RelevanceScorer<'a> {
+    fn new(terms: &[&'a TextQuery]) -> Self {
+        RelevanceScorer {
+            terms: terms.iter().map(|&term| (term, term.text.to_lowercase())).collect(),
+            now: chrono::Utc::now().timestamp(),
+            lower_filename: String::new(),
+        }
+    }
+
+    fn score(&mut self, tree: &FileTree, index: ElementId) -> i64 {
+        let filename = tree.get_filename(index);
+        self.lower_filename.clear();
+        self.lower_filename.extend(filename.chars().flat_map(char::to_lowercase));
+        let text = self.lower_filename.as_str();
+        let mut score = 0;
+
+        for (term, term_text) in &self.terms {
+            score += match text.find(term_text.as_str()) {
+                Some(_) if text == term_text => 100,
+                // Exact match without the extension
+                Some(_) if text.rsplit_once('.').is_some_and(|(stem, _)| stem == term_text) => 80,
+                Some(0) => 50,
+                Some(_) if matches_at_word_start(text, term_text) => 30,
+                Some(_) => 10,
+                // Path terms not in the filename matched one of the folders
+                None if term.match_path => 10,
+                // Only fuzzy terms match without containing the text, rank them by the number of typos
+                None => {
+                    let distance = fuzzy_distance(&term.text, text, term.case_sensitive)
+                        .unwrap_or(term_text.len());
+                    -10 * distance as i64
+                }
+            };
         }
+        score + element_score(tree, index, filename, self.now)
+    }
+}
+
+// Part of the relevance independent of the query
+fn element_score(tree: &FileTree, index: ElementId, filename: &str, now: i64) -> i64 {
+    let mut score = 0;
+
+    // Prefer short names in shallow folders
+    score -= filename.chars().count().min(100) as i64 / 4;
+    let mut depth = 0;
+    let mut parent = tree.parent(index);
+    while parent != ElementId::ROOT && depth < 100 {
+        depth += 1;
+        parent = tree.parent(parent);
     }
+    score -= 2 * depth;
+
+    // Prefer recently modified files
+    if let Some(date_modified) = tree.date_modified(index) {
+        let age_days = (now - filetime_to_unix(date_modified)) / 86_400;
+        score += match age_days {
+            ..0 => 0,
+            0..7 => 10,
+            7..30 => 5,
+            30..365 => 2,
+            _ => 0,
+        };
+    }
+    score
+}
+
+// Whether the term occurs at the start of a word, i.e. at the start or after a separator
+fn matches_at_word_start(text: &str, term: &str) -> bool {
+    text.match_indices(term).any(|(position, _)| {
+        text[..position]
+            .chars()
+            .next_back()
+            .is_none_or(|before| !before.is_alphanumeric())
+    })
 }
 
 #[cfg(test)]
@@ -281,4 +524,76 @@ mod tests {
         );
         assert_eq!(indices, vec![element2, element1, element3, element4]);
     }
+
+    #[test]
+    fn test_update() {
+        let mut tree = FileTree::with_capacity(10);
+        let b = tree.add_or_update_recursive("dir/b.txt", Some(20), Some(3), None, 0);
+        let d = tree.add_or_update_recursive("dir/d.txt", Some(40), Some(1), None, 0);
+        let sorter = Sorter::new();
+        sorter.prepare_all(&tree);
+
+        // Add new elements, including a new folder, and change the size of an existing one
+        let len_before = tree.len();
+        let a = tree.add_or_update_recursive("dir/a.txt", Some(30), None, None, 0);
+        let c = tree.add_or_update_recursive("new/c.txt", Some(10), Some(2), None, 0);
+        tree.add_or_update_recursive("dir/d.txt", Some(5), Some(1), None, 0);
+        let mut changed: Vec<ElementId> = (len_before..tree.len()).map(ElementId::new).collect();
+        changed.push(d);
+        sorter.update(&changed);
+        assert_eq!(sorter.changed.lock().unwrap().len(), changed.len());
+
+        // The patched orders still sort all elements correctly
+        for field in [SortField::Filename, SortField::Size, SortField::DateModified, SortField::DateCreated] {
+            let mut sorted: Vec<ElementId> = tree.ids().collect();
+            sorter.sort_by(&tree, &mut sorted, field, SortOrder::Ascending);
+            assert!(
+                sorted.windows(2).all(|pair| cmp_elements(&tree, pair[0], pair[1], field).is_le()),
+                "{:?}",
+                field
+            );
+            sorted.sort_unstable();
+            assert_eq!(sorted, tree.ids().collect::<Vec<_>>());
+        }
+        let mut files = vec![a, b, c, d];
+        sorter.sort_by(&tree, &mut files, SortField::Size, SortOrder::Ascending);
+        assert_eq!(files, vec![d, c, b, a]);
+        sorter.sort_by(&tree, &mut files, SortField::Filename, SortOrder::Ascending);
+        assert_eq!(files, vec![a, b, c, d]);
+    }
+
+    #[test]
+    fn test_relevance() {
+        let mut tree = FileTree::with_capacity(10);
+        let now = (chrono::Utc::now().timestamp() + 11_644_473_600) * 10_000_000;
+
+        let contains = tree.add_or_update_recursive("docs/myreport.doc", None, None, None, 0);
+        let word_start = tree.add_or_update_recursive("docs/old/annual_report.txt", None, None, None, 0);
+        let prefix = tree.add_or_update_recursive("docs/reports_2023.xlsx", None, None, None, 0);
+        let deep = tree.add_or_update_recursive("a/b/c/report.pdf", None, None, None, 0);
+        let old = tree.add_or_update_recursive("docs/report.pdf", None, None, None, 0);
+        let recent = tree.add_or_update_recursive("misc/report.pdf", None, Some(now), None, 0);
+
+        let sorter = Sorter::new();
+        let query = crate::query::query_parser::parse_query("report");
+        let mut indices = vec![contains, word_start, prefix, deep, old, recent];
+        sorter.sort_by_relevance(&tree, &mut indices, &query.text_terms(), SortOrder::Descending);
+        assert_eq!(indices, vec![recent, old, deep, prefix, word_start, contains]);
+
+        sorter.sort_by_relevance(&tree, &mut indices, &query.text_terms(), SortOrder::Ascending);
+        assert_eq!(indices, vec![contains, word_start, prefix, deep, old, recent]);
+
+        // Only the top results are sorted, the others are still all there
+        sorter.sort_top_by_relevance(&tree, &mut indices, &query.text_terms(), SortOrder::Descending, 2);
+        assert_eq!(indices[..2], [recent, old]);
+        let mut rest = indices[2..].to_vec();
+        rest.sort_unstable();
+        assert_eq!(rest, vec![contains, word_start, prefix, deep]);
+
+        // Typos rank below exact matches
+        let query = crate::query::query_parser::parse_query("fuzzy:reprot");
+        let mut indices = vec![deep, old];
+        sorter.sort_by_relevance(&tree, &mut indices, &query.text_terms(), SortOrder::Descending);
+        assert_eq!(indices, vec![old, deep]);
+    }
 }
@@ -5,8 +5,13 @@ use serde::{Deserialize, Serialize};
 use std::process::{self};
 use std::sync::Mutex;
 use std::time::Instant;
-use tankseek_core::file_tree;
+use tankseek_core::file_tree::{self, ElementId};
+use tankseek_core::file_type::FileCategory;
+use tankseek_core::indexer::bigram_index::default_threads;
 use tankseek_core::loader;
+use tankseek_core::query::completion;
+use tankseek_core::query::highlighter::Highlights;
+use tankseek_core::query::macros::{self, QueryMacro};
 use tankseek_core::searcher;
 use tankseek_core::sorter;
 
@@ -18,20 +23,20 @@ struct FileResult {
     date_modified: Option<i64>,
     date_created: Option<i64>,
     attributes: u32,
+    category: FileCategory,
+    highlights: Highlights,
 }
 impl FileResult {
-    fn from_element<T: AsRef<str>, U: AsRef<str>>(
-        element: &file_tree::Element,
-        path: T,
-        filename: U,
-    ) -> Self {
+    fn from_element<T: AsRef<str>>(element: file_tree::ElementRef<'_>, path: T, highlights: Highlights) -> Self {
         FileResult {
-            name: filename.as_ref().to_string(),
+            name: element.name().to_string(),
             path: path.as_ref().to_string(),
-            size: element.size,
-            date_modified: element.date_modified,
-            date_created: element.date_created,
-            attributes: element.attributes,
+            size: element.size(),
+            date_modified: element.date_modified(),
+            date_created: element.date_created(),
+            attributes: element.attributes(),
+            category: FileCategory::of_element(element),
+            highlights,
         }
     }
 }
@@ -44,9 +49,29 @@ struct SearchResult {
     time_taken: u128,
 }
 
+#[derive(Serialize)]
+struct MacroInfo {
+    name: String,
+    expansion: String,
+    description: Option<String>,
+    takes_argument: bool,
+}
+impl From<&QueryMacro> for MacroInfo {
+    fn from(query_macro: &QueryMacro) -> Self {
+        MacroInfo {
+            name: query_macro.name.clone(),
+            expansion: query_macro.expansion.clone(),
+            description: query_macro.description.clone(),
+            takes_argument: query_macro.takes_argument(),
+        }
+    }
+}
+
 struct SearchCache {
     query: String,
-    indices: Vec<usize>,
+    indices: Vec<ElementId>,
+    // Number of leading indices in order, see Searcher::search_top
+    sorted: usize,
     sort_by: Option<SortField>,
     sort_order: Option<SortOrder>,
 }
@@ -69,14 +94,12 @@ fn search(
     let time_start = Instant::now();
     let result_indices;
 
-    // Normalize the query to lowercase for case-insensitive search
-    let query = query.to_lowercase();
-
     let sort_by: Option<SortField> = match sort_by.as_deref() {
         Some("filename") => Some(SortField::Filename),
         Some("date_modified") => Some(SortField::DateModified),
         Some("date_created") => Some(SortField::DateCreated),
         Some("size") => Some(SortField::Size),
+        Some("relevance") => Some(SortField::Relevance),
         _ => None, // Default to None if no valid sort field is provided
     };
     let sort_order: Option<SortOrder> = match sort_order.as_deref() {
@@ -85,50 +108,56 @@ fn search(
         _ => None, // Default to None if no valid sort order is provided
     };
 
+    // Results have to be in order up to the end of the requested page. Sort some pages ahead so
+    // scrolling doesn't search again for every page.
+    let needed = offset.unwrap_or(0) + 100;
     // Check if the query is cached
     let mut cache_guard = last_search_cache.search.lock().unwrap();
     if let Some(cache) = cache_guard.as_ref()
         && cache.query == query
         && cache.sort_by == sort_by
         && cache.sort_order == sort_order
+        && cache.sorted >= needed.min(cache.indices.len())
     {
         result_indices = &cache.indices;
     } else {
         drop(cache_guard); // Release the lock before performing the search
 
         // Perform the search using the Searcher
-        let indices = searcher.search(&query, sort_by, sort_order);
+        let sorted = needed.next_multiple_of(1000);
+        let indices = searcher.search_top(&query, sort_by, sort_order, sorted);
 
         cache_guard = last_search_cache.search.lock().unwrap();
         cache_guard.replace(SearchCache {
             query: query.clone(),
-            indices: indices,
+            indices,
+            sorted,
             sort_by,
             sort_order,
         });
         result_indices = &cache_guard.as_ref().unwrap().indices;
     }
-    let mut result_elements = Vec::new();
     // Now we have the indices of the elements that match the query
-    // Prepare the results based on the indices
-    result_indices
+    // Prepare the results of the requested page based on the indices
+    let page_indices: Vec<ElementId> = result_indices
         .iter()
         .skip(offset.unwrap_or(0))
         .take(100)
-        .for_each(|&index| {
-            if let Some(element) = searcher.get(index) {
-                result_elements.push(element);
-            }
-        });
+        .copied()
+        .filter(|&index| searcher.get(index).is_some())
+        .collect();
+    let highlights = searcher.highlights(&query, &page_indices);
 
     // Convert the elements to FileResult
-    let results: Vec<_> = result_elements
-        .into_iter()
-        .map(|element| {
+    let results: Vec<_> = page_indices
+        .iter()
+        .zip(highlights)
+        .map(|(&index, highlights)| {
+            let element = searcher.get(index).unwrap();
             FileResult::from_element(
-                &element,
-                searcher.get_file_tree().get_full_path(element.parent),
-                searcher.get_file_tree().filename_as_str(&element.filename),
+                element,
+                searcher.get_file_tree().get_full_path(element.parent()),
+                highlights,
             )
         })
         .collect();
@@ -147,20 +176,86 @@ fn search(
     }
 }
 
+#[get("/macros")]
+fn list_macros(searcher: &rocket::State<Searcher>) -> String {
+    let macros: Vec<MacroInfo> = searcher
+        .query_options
+        .macros
+        .iter()
+        .map(MacroInfo::from)
+        .collect();
+    match serde_json::to_string(&macros) {
+        Ok(json) => json,
+        Err(e) => format!("Error serializing macros: {}", e),
+    }
+}
+
+#[get("/complete?<query>&<cursor>")]
+fn complete(query: String, cursor: Option<usize>, searcher: &rocket::State<Searcher>) -> String {
+    // Complete at the end of the query if no cursor position is given
+    let cursor = cursor.unwrap_or(query.chars().count());
+    let completions = completion::complete(
+        &query,
+        cursor,
+        searcher.get_file_tree(),
+        &searcher.query_options.macros,
+        20,
+    );
+    match serde_json::to_string(&completions) {
+        Ok(json) => json,
+        Err(e) => format!("Error serializing completions: {}", e),
+    }
+}
+
+#[get("/typeahead?<prefix>&<limit>")]
+fn typeahead(prefix: String, limit: Option<usize>, searcher: &rocket::State<Searcher>) -> String {
+    let filenames = searcher.typeahead(&prefix, limit.unwrap_or(10).min(100));
+    match serde_json::to_string(&filenames) {
+        Ok(json) => json,
+        Err(e) => format!("Error serializing filenames: {}", e),
+    }
+}
+
+// Approximate memory used by the file tree, the indexes and the cached sort orders
+#[get("/memory")]
+fn memory(searcher: &rocket::State<Searcher>) -> String {
+    match serde_json::to_string(&searcher.memory_usage()) {
+        Ok(json) => json,
+        Err(e) => format!("Error serializing memory usage: {}", e),
+    }
+}
+
 #[launch]
 fn rocket() -> _ {
     println!("Reading file list...");
     let start = Instant::now();
-    match loader::efu::import_efu("filelist.efu") {
-        Ok(tree) => {
+    // Threads for building the index, all available by default
+    let threads = match std::env::var("TANKSEEK_INDEX_THREADS") {
+        Ok(value) => value.parse().ok().filter(|&threads| threads > 0).unwrap_or_else(|| {
+            eprintln!("Invalid TANKSEEK_INDEX_THREADS '{}', using all available threads", value);
+            default_threads()
+        }),
+        Err(_) => default_threads(),
+    };
+    // Reuses the index from the last start unless filelist.efu has changed since
+    match loader::snapshot::load_or_rebuild("filelist.efu", "filelist.snapshot", threads) {
+        Ok(mut searcher) => {
             println!(
                 "Read {} records from filelist.efu in {:?}",
-                tree.len(),
+                searcher.file_tree.len(),
                 start.elapsed()
             );
 
-            // Create searcher
-            let searcher = Searcher::from_file_tree(tree);
+            // Load user-defined search macros if present
+            if std::path::Path::new("macros.json").exists() {
+                match macros::load_macros("macros.json") {
+                    Ok(macros) => {
+                        println!("Loaded {} search macros from macros.json", macros.len());
+                        searcher.query_options.macros = macros;
+                    }
+                    Err(e) => eprintln!("Error reading macros.json: {}", e),
+                }
+            }
 
             //  exit(0); // Exit successfully after reading the file list
             rocket::build()
@@ -168,7 +263,7 @@ fn rocket() -> _ {
                 .manage(LastSearchCache {
                     search: Mutex::new(None),
                 })
-                .mount("/", routes![search])
+                .mount("/", routes![search, list_macros, complete, typeahead, memory])
                 .mount("/", FileServer::from(relative!("public")))
         }
         Err(e) => {